@@ -20,18 +20,24 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
 // THE SOFTWARE.
 
-use flows::FlowsManager;
-use hhd::HHDManager;
+use detection::{DetectionAction, DetectionManager, DetectionSettings};
+use event::{Event, EventManager, MAX_NUMBER_OF_SUBSCRIBERS};
+use flows::{percent_decode, FlowsEvictionPolicy, FlowsManager, FlowsQuery, DEFAULT_SESSION, HHD_SESSION_PREFIX};
+use hhd::{create_policy, HHDManager, HHDMetric, HHDPolicySettings, HHDThreshold, HeavyHitterPolicy, DEFAULT_HHD_SESSION};
 use iron::{Iron, IronResult, Request, Response};
+use iron::headers::{CacheControl, CacheDirective};
 use iron::mime::{Mime, SubLevel, TopLevel};
+use iron::response::WriteBody;
 use iron::status;
 use l2::{DivertType, L2Manager};
 use metrics::MetricsCollector;
 use router::Router;
 use rustc_serialize::json;
-use std::io::Read;
+use std::io::{self, Read, Write};
 use std::sync::Mutex;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
 use std::thread;
+use std::time::Duration;
 
 pub struct APIManager {
     listening_port: u16,
@@ -47,14 +53,31 @@ pub struct Divert {
 
 #[derive(Clone, Debug, RustcEncodable, RustcDecodable)]
 pub struct Flows {
-    pub port_ingress: u32,
+    pub session: Option<String>,
+    pub port_ingress: Option<u32>,
+    pub ports_ingress: Option<Vec<u32>>,
     pub max_number_of_flows: u32,
-    pub time_window_in_seconds: u32,
+    pub time_window_in_seconds: Option<u32>,
+    pub continuous: Option<bool>,
+    pub eviction_policy: Option<String>,
 }
 
 #[derive(Clone, Debug, RustcEncodable, RustcDecodable)]
 pub struct Hhd {
     pub port_ingress: u32,
+    pub analysis_window_in_seconds: Option<f64>,
+    pub running: Option<bool>,
+    pub metric: Option<String>,
+    pub policy: Option<String>,
+    pub threshold: Option<f64>,
+    pub threshold_unit: Option<String>,
+    pub k: Option<usize>,
+    pub prefix_length: Option<u16>,
+    pub prefix_lengths: Option<Vec<u16>>,
+    pub change_threshold: Option<f64>,
+    pub change_threshold_unit: Option<String>,
+    pub change_ratio: Option<f64>,
+    pub max_number_of_diverts: Option<usize>,
 }
 
 #[derive(Clone, Debug, RustcEncodable, RustcDecodable)]
@@ -63,6 +86,61 @@ pub struct HhdDivert {
     pub port_ingress_divert: u32,
     pub divert_ingress: u32,
     pub divert_egress: u32,
+    pub analysis_window_in_seconds: Option<f64>,
+    pub running: Option<bool>,
+    pub metric: Option<String>,
+    pub policy: Option<String>,
+    pub threshold: Option<f64>,
+    pub threshold_unit: Option<String>,
+    pub k: Option<usize>,
+    pub prefix_length: Option<u16>,
+    pub prefix_lengths: Option<Vec<u16>>,
+    pub change_threshold: Option<f64>,
+    pub change_threshold_unit: Option<String>,
+    pub change_ratio: Option<f64>,
+    pub max_number_of_diverts: Option<usize>,
+    pub switch_margin_percent: Option<f64>,
+    pub switch_after_windows: Option<u32>,
+    pub min_hold_time_in_seconds: Option<u32>,
+    pub divert_heavy_changes: Option<bool>,
+    pub observe_only: Option<bool>,
+}
+
+#[derive(Clone, Debug, RustcEncodable, RustcDecodable)]
+pub struct HhdSession {
+    pub ports_ingress: Vec<u32>,
+    pub divert_type: Option<String>,
+    pub divert_ingress: Option<u32>,
+    pub divert_egress: Option<u32>,
+    pub analysis_window_in_seconds: Option<f64>,
+    pub running: Option<bool>,
+    pub metric: Option<String>,
+    pub policy: Option<String>,
+    pub threshold: Option<f64>,
+    pub threshold_unit: Option<String>,
+    pub k: Option<usize>,
+    pub prefix_length: Option<u16>,
+    pub prefix_lengths: Option<Vec<u16>>,
+    pub change_threshold: Option<f64>,
+    pub change_threshold_unit: Option<String>,
+    pub change_ratio: Option<f64>,
+    pub max_number_of_diverts: Option<usize>,
+    pub switch_margin_percent: Option<f64>,
+    pub switch_after_windows: Option<u32>,
+    pub min_hold_time_in_seconds: Option<u32>,
+    pub divert_heavy_changes: Option<bool>,
+    pub observe_only: Option<bool>,
+}
+
+#[derive(Clone, Debug, RustcEncodable, RustcDecodable)]
+pub struct Detections {
+    pub interval_in_seconds: Option<u32>,
+    pub window_in_seconds: Option<u32>,
+    pub superspreader_threshold: Option<usize>,
+    pub ddos_target_threshold: Option<usize>,
+    pub action: Option<String>,
+    pub divert_egress: Option<u32>,
+    pub max_number_of_actions: Option<usize>,
 }
 
 #[derive(Clone, Debug, RustcEncodable)]
@@ -70,6 +148,9 @@ struct SimpleResponse {
     result: String,
 }
 
+// the fixed /hhd routes take these names, /hhd/{name} can't reach a session named like them
+const RESERVED_HHD_SESSION_NAMES: [&'static str; 5] = ["dest", "src", "results", "sessions", "status"];
+
 lazy_static! {
     static ref MANAGER: Mutex<APIManager> = Mutex::new(APIManager{listening_port: 0});
 }
@@ -83,17 +164,36 @@ impl APIManager {
 
             router.get("/admin/ping", handle_ping, "pingpong");
             router.get("/metrics", handle_get_metrics, "get metrics");
+            router.get("/events", handle_get_events, "get events");
             router.post("/divert/dest", handle_set_divert_dest, "post divert dest");
             router.post("/divert/src", handle_set_divert_src, "post divert src");
             router.patch("/divert/dest", handle_patch_divert_dest, "patch divert dest");
             router.patch("/divert/src", handle_patch_divert_src, "patch divert src");
             router.delete("/divert", handle_reset_divert, "reset divert");
             router.get("/flows", handle_get_learned_flows, "get flows");
+            router.get("/flows/history", handle_get_flow_history, "get flow history");
+            router.get("/flows/sessions", handle_get_flow_sessions, "get flow sessions");
+            router.get("/flows/:session", handle_get_learned_flows_for_session, "get flows for session");
+            router.delete("/flows/:session", handle_remove_flow_session, "delete flow session");
+            router.get("/flows/active/:from/:to", handle_get_active_flows, "get active flows");
             router.post("/flows", handle_set_flow_learning, "post flows");
             router.post("/hhd", handle_set_hhd, "post hhd");
             router.post("/hhd/dest", handle_set_hhd_divert_dest, "post hhd divert dest");
             router.post("/hhd/src", handle_set_hhd_divert_src, "post hhd divert src");
+            router.get("/hhd/results", handle_get_hhd_results, "get hhd results");
+            router.get("/hhd/results/latest", handle_get_latest_hhd_result, "get latest hhd result");
             router.delete("/hhd", handle_reset_hhd, "reset hhd");
+            router.get("/hhd/sessions", handle_get_hhd_sessions, "get hhd sessions");
+            router.get("/hhd/status", handle_get_hhd_status, "get hhd status");
+            router.get("/hhd/:session", handle_get_hhd_session, "get hhd session");
+            router.post("/hhd/:session", handle_set_hhd_session, "post hhd session");
+            router.delete("/hhd/:session", handle_remove_hhd_session, "delete hhd session");
+            router.get("/hhd/:session/results", handle_get_hhd_session_results, "get hhd session results");
+            router.get("/hhd/:session/results/latest", handle_get_latest_hhd_session_result, "get latest hhd session result");
+            router.get("/detections", handle_get_detections, "get detections");
+            router.get("/detections/status", handle_get_detection_status, "get detection status");
+            router.post("/detections", handle_set_detections, "post detections");
+            router.delete("/detections", handle_stop_detections, "delete detections");
             Iron::new(router).http(format!("0.0.0.0:{}", listening_port)).unwrap();
         });
     }
@@ -109,6 +209,83 @@ fn handle_get_metrics(_request: &mut Request) -> IronResult<Response> {
     Ok(Response::with((content_type, status::Ok, json::encode(&result).unwrap())))
 }
 
+// streams events as server-sent events until the client goes away or falls behind
+struct EventStream {
+    subscriber_id: u64,
+    receiver: Receiver<Event>,
+    event_types: Option<Vec<String>>,
+}
+
+impl Drop for EventStream {
+    fn drop(&mut self) {
+        EventManager::unsubscribe(self.subscriber_id);
+    }
+}
+
+impl WriteBody for EventStream {
+    fn write_body(&mut self, body: &mut Write) -> io::Result<()> {
+        loop {
+            match self.receiver.recv_timeout(Duration::from_secs(15)) {
+                Ok(event) => {
+                    let selected = match self.event_types {
+                        Some(ref event_types) => event_types.iter().any(|event_type| event_type == event.event_type.name()),
+                        None => true,
+                    };
+                    if selected {
+                        write!(body, "event: {}\ndata: {}\n\n", event.event_type.name(), event.to_json())?;
+                        body.flush()?;
+                    }
+                }
+                // comment lines keep proxies from closing the connection and detect clients that went away
+                Err(RecvTimeoutError::Timeout) => {
+                    write!(body, ": keep-alive\n\n")?;
+                    body.flush()?;
+                }
+                Err(RecvTimeoutError::Disconnected) => return Ok(()),
+            }
+        }
+    }
+}
+
+fn handle_get_events(request: &mut Request) -> IronResult<Response> {
+    let mut event_types: Option<Vec<String>> = None;
+    for param in request.url.query().unwrap_or("").split('&').filter(|param| !param.is_empty()) {
+        let mut parts = param.splitn(2, '=');
+        let key = match percent_decode(parts.next().unwrap()) {
+            Ok(key) => key,
+            Err(err) => return respond_with_error(status::BadRequest, err),
+        };
+        if key != "types" || event_types.is_some() {
+            continue;
+        }
+
+        match percent_decode(parts.next().unwrap_or("")) {
+            Ok(value) => event_types = Some(value.split(',').map(|event_type| event_type.to_string()).collect()),
+            Err(err) => return respond_with_error(status::BadRequest, err),
+        }
+    }
+
+    let (subscriber_id, receiver) = match EventManager::subscribe() {
+        Some(subscription) => subscription,
+        None => {
+            return respond_with_error(
+                status::ServiceUnavailable,
+                format!("at most {} event subscribers at the same time", MAX_NUMBER_OF_SUBSCRIBERS),
+            )
+        }
+    };
+
+    let content_type = Mime(TopLevel::Text, SubLevel::Ext("event-stream".to_string()), Vec::new());
+    let mut response = Response::with((content_type, status::Ok));
+    response.headers.set(CacheControl(vec![CacheDirective::NoCache]));
+    response.body = Some(Box::new(EventStream {
+        subscriber_id: subscriber_id,
+        receiver: receiver,
+        event_types: event_types,
+    }));
+    Ok(response)
+}
+
 fn handle_set_divert_dest(request: &mut Request) -> IronResult<Response> {
     handle_set_divert(request, DivertType::IPDest)
 }
@@ -171,12 +348,79 @@ fn handle_reset_divert(_request: &mut Request) -> IronResult<Response> {
     Ok(Response::with((content_type, status::Ok, json::encode(&response).unwrap())))
 }
 
-fn handle_get_learned_flows(_request: &mut Request) -> IronResult<Response> {
-    let result = FlowsManager::get_learned_flows();
+fn handle_get_learned_flows(request: &mut Request) -> IronResult<Response> {
+    let query = match request.url.query() {
+        Some(query) => FlowsQuery::from_query_string(query),
+        None => Ok(FlowsQuery::new()),
+    };
+
+    match query {
+        Ok(query) => {
+            let (total, result) = query.apply(FlowsManager::get_learned_flows());
+            let content_type = Mime(TopLevel::Application, SubLevel::Json, Vec::new());
+            let mut response = Response::with((content_type, status::Ok, json::encode(&result).unwrap()));
+            response.headers.set_raw("X-Total-Count", vec![total.to_string().into_bytes()]);
+            Ok(response)
+        }
+        Err(err) => respond_with_error(status::BadRequest, err),
+    }
+}
+
+fn handle_get_flow_history(_request: &mut Request) -> IronResult<Response> {
+    let result = FlowsManager::get_flow_history();
     let content_type = Mime(TopLevel::Application, SubLevel::Json, Vec::new());
     Ok(Response::with((content_type, status::Ok, json::encode(&result).unwrap())))
 }
 
+fn handle_get_active_flows(request: &mut Request) -> IronResult<Response> {
+    let (from, to) = {
+        let params = request.extensions.get::<Router>().unwrap();
+        (params.find("from").unwrap_or("").parse::<u64>(), params.find("to").unwrap_or("").parse::<u64>())
+    };
+
+    match (from, to) {
+        (Ok(from), Ok(to)) => {
+            let result = FlowsManager::get_flows_active_between(from, to);
+            let content_type = Mime(TopLevel::Application, SubLevel::Json, Vec::new());
+            Ok(Response::with((content_type, status::Ok, json::encode(&result).unwrap())))
+        }
+        _ => respond_with_error(status::BadRequest, "from and to have to be timestamps in milliseconds".to_string()),
+    }
+}
+
+fn handle_get_flow_sessions(_request: &mut Request) -> IronResult<Response> {
+    let result = FlowsManager::get_sessions();
+    let content_type = Mime(TopLevel::Application, SubLevel::Json, Vec::new());
+    Ok(Response::with((content_type, status::Ok, json::encode(&result).unwrap())))
+}
+
+fn handle_get_learned_flows_for_session(request: &mut Request) -> IronResult<Response> {
+    let session = request.extensions.get::<Router>().unwrap().find("session").unwrap_or("").to_string();
+
+    match FlowsManager::get_learned_flows_for_session(&session) {
+        Some(result) => {
+            let content_type = Mime(TopLevel::Application, SubLevel::Json, Vec::new());
+            Ok(Response::with((content_type, status::Ok, json::encode(&result).unwrap())))
+        }
+        None => respond_with_error(status::NotFound, format!("Flows session {} not found", session)),
+    }
+}
+
+fn handle_remove_flow_session(request: &mut Request) -> IronResult<Response> {
+    let session = request.extensions.get::<Router>().unwrap().find("session").unwrap_or("").to_string();
+
+    println!("Remove Flows session {}", session);
+
+    if session.starts_with(HHD_SESSION_PREFIX) {
+        return respond_with_error(status::BadRequest, format!("Flows session {} belongs to HHD", session));
+    }
+
+    match FlowsManager::remove_session(&session) {
+        true => respond_with_done(),
+        false => respond_with_error(status::NotFound, format!("Flows session {} not found", session)),
+    }
+}
+
 fn handle_set_flow_learning(request: &mut Request) -> IronResult<Response> {
     let mut body = String::new();
     request.body.read_to_string(&mut body).unwrap();
@@ -184,15 +428,51 @@ fn handle_set_flow_learning(request: &mut Request) -> IronResult<Response> {
 
     println!("Set {:?}", flows);
 
-    FlowsManager::set_flow_learning_for_time_window(flows.port_ingress, flows.max_number_of_flows, flows.time_window_in_seconds);
+    let mut ports_ingress = flows.ports_ingress.clone().unwrap_or(Vec::new());
+    if let Some(port_ingress) = flows.port_ingress {
+        ports_ingress.push(port_ingress);
+    }
+    if ports_ingress.is_empty() {
+        return respond_with_error(status::BadRequest, "port_ingress or ports_ingress has to be set".to_string());
+    }
+    let session = flows.session.clone().unwrap_or(DEFAULT_SESSION.to_string());
+    if session.starts_with(HHD_SESSION_PREFIX) {
+        return respond_with_error(
+            status::BadRequest,
+            format!("Flows session names starting with {} are reserved for HHD", HHD_SESSION_PREFIX),
+        );
+    }
+    let eviction_policy = match flows.eviction_policy {
+        Some(ref name) => match FlowsEvictionPolicy::from_name(name) {
+            Some(eviction_policy) => Some(eviction_policy),
+            None => {
+                return respond_with_error(status::BadRequest, format!("unknown eviction policy {}", name));
+            }
+        },
+        None => None,
+    };
 
-    let response = SimpleResponse {
-        result: "done".to_string(),
+    let result = match (flows.continuous.unwrap_or(false), flows.time_window_in_seconds) {
+        (true, _) => FlowsManager::set_continuous_flow_learning(&session, &ports_ingress, flows.max_number_of_flows, eviction_policy),
+        (false, Some(time_window_in_seconds)) => FlowsManager::set_flow_learning_for_time_window(
+            &session,
+            &ports_ingress,
+            flows.max_number_of_flows,
+            eviction_policy,
+            time_window_in_seconds,
+        ),
+        (false, None) => {
+            return respond_with_error(status::BadRequest, "either time_window_in_seconds or continuous has to be set".to_string());
+        }
     };
-    let content_type = Mime(TopLevel::Application, SubLevel::Json, Vec::new());
-    Ok(Response::with((content_type, status::Ok, json::encode(&response).unwrap())))
+
+    match result {
+        Ok(()) => respond_with_done(),
+        Err(err) => respond_with_error(status::Conflict, err),
+    }
 }
 
+// the original endpoints configure the default session
 fn handle_set_hhd(request: &mut Request) -> IronResult<Response> {
     let mut body = String::new();
     request.body.read_to_string(&mut body).unwrap();
@@ -200,33 +480,326 @@ fn handle_set_hhd(request: &mut Request) -> IronResult<Response> {
 
     println!("{:?}", hhd);
 
-    HHDManager::set_hhd(hhd.port_ingress);
-
-    let response = SimpleResponse {
-        result: "done".to_string(),
+    let session = HhdSession {
+        ports_ingress: vec![hhd.port_ingress],
+        divert_type: None,
+        divert_ingress: None,
+        divert_egress: None,
+        analysis_window_in_seconds: hhd.analysis_window_in_seconds,
+        running: hhd.running,
+        metric: hhd.metric,
+        policy: hhd.policy,
+        threshold: hhd.threshold,
+        threshold_unit: hhd.threshold_unit,
+        k: hhd.k,
+        prefix_length: hhd.prefix_length,
+        prefix_lengths: hhd.prefix_lengths,
+        change_threshold: hhd.change_threshold,
+        change_threshold_unit: hhd.change_threshold_unit,
+        change_ratio: hhd.change_ratio,
+        max_number_of_diverts: hhd.max_number_of_diverts,
+        switch_margin_percent: None,
+        switch_after_windows: None,
+        min_hold_time_in_seconds: None,
+        divert_heavy_changes: None,
+        observe_only: None,
     };
-    let content_type = Mime(TopLevel::Application, SubLevel::Json, Vec::new());
-    Ok(Response::with((content_type, status::Ok, json::encode(&response).unwrap())))
+    set_hhd_session(DEFAULT_HHD_SESSION, &session)
 }
 
 fn handle_set_hhd_divert_dest(request: &mut Request) -> IronResult<Response> {
-    handle_set_hhd_divert(request, DivertType::IPDest)
+    handle_set_hhd_divert(request, "dest")
 }
 
 fn handle_set_hhd_divert_src(request: &mut Request) -> IronResult<Response> {
-    handle_set_hhd_divert(request, DivertType::IPSrc)
+    handle_set_hhd_divert(request, "src")
 }
 
-fn handle_set_hhd_divert(request: &mut Request, divert_type: DivertType) -> IronResult<Response> {
+fn handle_set_hhd_divert(request: &mut Request, divert_type: &str) -> IronResult<Response> {
     let mut body = String::new();
     request.body.read_to_string(&mut body).unwrap();
     let hhd: HhdDivert = json::decode(&body).unwrap();
 
     println!("{:?}", hhd);
 
-    HHDManager::set_hhd(hhd.port_ingress);
-    HHDManager::set_hhd(hhd.port_ingress_divert);
-    HHDManager::run_hhd_divert(hhd.divert_ingress, hhd.divert_egress, divert_type);
+    let session = HhdSession {
+        ports_ingress: vec![hhd.port_ingress, hhd.port_ingress_divert],
+        divert_type: Some(divert_type.to_string()),
+        divert_ingress: Some(hhd.divert_ingress),
+        divert_egress: Some(hhd.divert_egress),
+        analysis_window_in_seconds: hhd.analysis_window_in_seconds,
+        running: hhd.running,
+        metric: hhd.metric,
+        policy: hhd.policy,
+        threshold: hhd.threshold,
+        threshold_unit: hhd.threshold_unit,
+        k: hhd.k,
+        prefix_length: hhd.prefix_length,
+        prefix_lengths: hhd.prefix_lengths,
+        change_threshold: hhd.change_threshold,
+        change_threshold_unit: hhd.change_threshold_unit,
+        change_ratio: hhd.change_ratio,
+        max_number_of_diverts: hhd.max_number_of_diverts,
+        switch_margin_percent: hhd.switch_margin_percent,
+        switch_after_windows: hhd.switch_after_windows,
+        min_hold_time_in_seconds: hhd.min_hold_time_in_seconds,
+        divert_heavy_changes: hhd.divert_heavy_changes,
+        observe_only: hhd.observe_only,
+    };
+    set_hhd_session(DEFAULT_HHD_SESSION, &session)
+}
+
+fn handle_set_hhd_session(request: &mut Request) -> IronResult<Response> {
+    let session_name = request.extensions.get::<Router>().unwrap().find("session").unwrap_or("").to_string();
+
+    let mut body = String::new();
+    request.body.read_to_string(&mut body).unwrap();
+    let hhd: HhdSession = match json::decode(&body) {
+        Ok(hhd) => hhd,
+        Err(err) => return respond_with_error(status::BadRequest, format!("invalid HHD session: {}", err)),
+    };
+
+    println!("HHD session {}: {:?}", session_name, hhd);
+
+    set_hhd_session(&session_name, &hhd)
+}
+
+// counts on all ports of the session, diverts only if divert type and ports are set
+fn set_hhd_session(session_name: &str, hhd: &HhdSession) -> IronResult<Response> {
+    if RESERVED_HHD_SESSION_NAMES.contains(&session_name) {
+        return respond_with_error(status::BadRequest, format!("HHD session name {} is reserved", session_name));
+    }
+    let divert_type = match hhd.divert_type {
+        Some(ref name) if name == "src" => Some(DivertType::IPSrc),
+        Some(ref name) if name == "dest" => Some(DivertType::IPDest),
+        Some(ref name) => return respond_with_error(status::BadRequest, format!("unknown divert type {}, use src or dest", name)),
+        None => None,
+    };
+    let divert = match (divert_type, hhd.divert_ingress, hhd.divert_egress) {
+        (Some(divert_type), Some(divert_ingress), Some(divert_egress)) => Some((divert_type, divert_ingress, divert_egress)),
+        (None, None, None) => None,
+        _ => {
+            return respond_with_error(
+                status::BadRequest,
+                "divert_type, divert_ingress and divert_egress have to be set together".to_string(),
+            )
+        }
+    };
+    // everything gets validated before the session changes
+    let metric = match read_hhd_metric(&hhd.metric) {
+        Ok(metric) => metric,
+        Err(err) => return respond_with_error(status::BadRequest, err),
+    };
+    match hhd.change_ratio {
+        Some(change_ratio) if !change_ratio.is_finite() || change_ratio <= 0.0 => {
+            return respond_with_error(status::BadRequest, "change_ratio has to be a number above 0".to_string())
+        }
+        _ => {}
+    }
+    match hhd.switch_margin_percent {
+        Some(switch_margin_percent) if !switch_margin_percent.is_finite() || switch_margin_percent < 0.0 => {
+            return respond_with_error(status::BadRequest, "switch_margin_percent has to be a number of at least 0".to_string())
+        }
+        _ => {}
+    }
+    let policy = match read_hhd_policy(
+        &hhd.policy,
+        hhd.threshold,
+        &hhd.threshold_unit,
+        hhd.k,
+        hhd.prefix_length,
+        &hhd.prefix_lengths,
+        hhd.change_ratio,
+    ) {
+        Ok(policy) => policy,
+        Err(err) => return respond_with_error(status::BadRequest, err),
+    };
+    let change_detection = match read_hhd_change_detection(hhd.change_threshold, &hhd.change_threshold_unit, hhd.change_ratio) {
+        Ok(change_detection) => change_detection,
+        Err(err) => return respond_with_error(status::BadRequest, err),
+    };
+    let analysis_window_in_millis = match hhd.analysis_window_in_seconds {
+        Some(analysis_window_in_seconds) if analysis_window_in_seconds > 0.0 => {
+            let analysis_window_in_millis = (analysis_window_in_seconds * 1000.0) as u64;
+            if let Err(err) = HHDManager::verify_analysis_window(analysis_window_in_millis) {
+                return respond_with_error(status::BadRequest, err);
+            }
+            Some(analysis_window_in_millis)
+        }
+        Some(_) => return respond_with_error(status::BadRequest, "analysis_window_in_seconds has to be positive".to_string()),
+        None => None,
+    };
+    if let Some((_, divert_ingress, divert_egress)) = divert {
+        if let Err(err) = HHDManager::verify_divert_ports(session_name, divert_ingress, divert_egress) {
+            return respond_with_error(status::Conflict, err);
+        }
+    }
+
+    // all ports get claimed or none, a new session only exists after that
+    if let Err(err) = HHDManager::set_hhd(session_name, &hhd.ports_ingress) {
+        return respond_with_error(status::Conflict, err);
+    }
+
+    if let Some(metric) = metric {
+        HHDManager::set_metric(session_name, metric);
+    }
+    if let Some(policy) = policy {
+        HHDManager::set_policy(session_name, policy);
+    }
+    if let Some(max_number_of_diverts) = hhd.max_number_of_diverts {
+        HHDManager::set_max_number_of_diverts(session_name, max_number_of_diverts);
+    }
+    if let Some((change_threshold, change_ratio)) = change_detection {
+        HHDManager::set_change_detection(session_name, change_threshold, change_ratio, hhd.divert_heavy_changes.unwrap_or(false));
+    }
+    if let Some(analysis_window_in_millis) = analysis_window_in_millis {
+        let _ = HHDManager::set_analysis_window(session_name, analysis_window_in_millis);
+    }
+    HHDManager::set_hysteresis(session_name, hhd.switch_margin_percent, hhd.switch_after_windows, hhd.min_hold_time_in_seconds);
+    if let Some((divert_type, divert_ingress, divert_egress)) = divert {
+        HHDManager::run_hhd_divert(session_name, divert_ingress, divert_egress, divert_type, hhd.observe_only.unwrap_or(false));
+    }
+    if let Some(running) = hhd.running {
+        HHDManager::set_running(session_name, running);
+    }
+    respond_with_done()
+}
+
+fn read_hhd_metric(metric: &Option<String>) -> Result<Option<HHDMetric>, String> {
+    match *metric {
+        Some(ref name) => match HHDMetric::from_name(name) {
+            Some(metric) => Ok(Some(metric)),
+            None => Err(format!("unknown metric {}, use packets or bytes", name)),
+        },
+        None => Ok(None),
+    }
+}
+
+// without policy the configured or last set policy stays, a threshold alone selects the threshold policy
+fn read_hhd_policy(
+    policy: &Option<String>,
+    threshold: Option<f64>,
+    threshold_unit: &Option<String>,
+    k: Option<usize>,
+    prefix_length: Option<u16>,
+    prefix_lengths: &Option<Vec<u16>>,
+    change_ratio: Option<f64>,
+) -> Result<Option<Box<HeavyHitterPolicy>>, String> {
+    let threshold = read_hhd_threshold(threshold, threshold_unit)?;
+
+    let name = match (policy.clone(), threshold) {
+        (Some(name), _) => name,
+        (None, Some(_)) => "threshold".to_string(),
+        (None, None) => return Ok(None),
+    };
+    let settings = HHDPolicySettings {
+        threshold: threshold,
+        k: k.unwrap_or(HHDManager::get_top_k()),
+        prefix_length: prefix_length.unwrap_or(24),
+        prefix_lengths: prefix_lengths.clone().unwrap_or(vec![32, 24, 16]),
+        change_ratio: change_ratio,
+    };
+    create_policy(&name, &settings).map(|policy| Some(policy))
+}
+
+// heavy change detection stays as is unless a change threshold or ratio is given
+fn read_hhd_change_detection(
+    change_threshold: Option<f64>,
+    change_threshold_unit: &Option<String>,
+    change_ratio: Option<f64>,
+) -> Result<Option<(Option<HHDThreshold>, Option<f64>)>, String> {
+    if change_threshold.is_none() && change_ratio.is_none() {
+        return Ok(None);
+    }
+
+    let change_threshold = read_hhd_threshold(change_threshold, change_threshold_unit)?;
+    Ok(Some((change_threshold, change_ratio)))
+}
+
+fn read_hhd_threshold(threshold: Option<f64>, threshold_unit: &Option<String>) -> Result<Option<HHDThreshold>, String> {
+    match threshold {
+        Some(value) => {
+            let unit = threshold_unit.clone().unwrap_or("per-window".to_string());
+            HHDThreshold::from_name(&unit, value).map(Some)
+        }
+        None => Ok(None),
+    }
+}
+
+fn handle_get_hhd_results(_request: &mut Request) -> IronResult<Response> {
+    respond_with_hhd_results(DEFAULT_HHD_SESSION)
+}
+
+fn handle_get_latest_hhd_result(_request: &mut Request) -> IronResult<Response> {
+    respond_with_latest_hhd_result(DEFAULT_HHD_SESSION)
+}
+
+fn handle_get_hhd_session_results(request: &mut Request) -> IronResult<Response> {
+    let session_name = request.extensions.get::<Router>().unwrap().find("session").unwrap_or("").to_string();
+    respond_with_hhd_results(&session_name)
+}
+
+fn handle_get_latest_hhd_session_result(request: &mut Request) -> IronResult<Response> {
+    let session_name = request.extensions.get::<Router>().unwrap().find("session").unwrap_or("").to_string();
+    respond_with_latest_hhd_result(&session_name)
+}
+
+// a session that was never set up has no results yet
+fn respond_with_hhd_results(session_name: &str) -> IronResult<Response> {
+    let result = HHDManager::get_results(session_name).unwrap_or(Vec::new());
+    let content_type = Mime(TopLevel::Application, SubLevel::Json, Vec::new());
+    Ok(Response::with((content_type, status::Ok, json::encode(&result).unwrap())))
+}
+
+fn respond_with_latest_hhd_result(session_name: &str) -> IronResult<Response> {
+    match HHDManager::get_latest_result(session_name) {
+        Some(result) => {
+            let content_type = Mime(TopLevel::Application, SubLevel::Json, Vec::new());
+            Ok(Response::with((content_type, status::Ok, json::encode(&result).unwrap())))
+        }
+        None => respond_with_error(status::NotFound, "no HHD results yet".to_string()),
+    }
+}
+
+fn handle_get_hhd_sessions(_request: &mut Request) -> IronResult<Response> {
+    let result = HHDManager::get_sessions();
+    let content_type = Mime(TopLevel::Application, SubLevel::Json, Vec::new());
+    Ok(Response::with((content_type, status::Ok, json::encode(&result).unwrap())))
+}
+
+fn handle_get_hhd_status(_request: &mut Request) -> IronResult<Response> {
+    let result = HHDManager::get_status();
+    let content_type = Mime(TopLevel::Application, SubLevel::Json, Vec::new());
+    Ok(Response::with((content_type, status::Ok, json::encode(&result).unwrap())))
+}
+
+fn handle_get_hhd_session(request: &mut Request) -> IronResult<Response> {
+    let session_name = request.extensions.get::<Router>().unwrap().find("session").unwrap_or("").to_string();
+
+    match HHDManager::get_session(&session_name) {
+        Some(result) => {
+            let content_type = Mime(TopLevel::Application, SubLevel::Json, Vec::new());
+            Ok(Response::with((content_type, status::Ok, json::encode(&result).unwrap())))
+        }
+        None => respond_with_error(status::NotFound, format!("HHD session {} not found", session_name)),
+    }
+}
+
+fn handle_remove_hhd_session(request: &mut Request) -> IronResult<Response> {
+    let session_name = request.extensions.get::<Router>().unwrap().find("session").unwrap_or("").to_string();
+
+    println!("Remove HHD session {}", session_name);
+
+    match HHDManager::remove_session(&session_name) {
+        true => respond_with_done(),
+        false => respond_with_error(status::NotFound, format!("HHD session {} not found", session_name)),
+    }
+}
+
+fn handle_reset_hhd(_request: &mut Request) -> IronResult<Response> {
+    println!("Reset HHD Table");
+
+    HHDManager::remove_all_sessions();
 
     let response = SimpleResponse {
         result: "done".to_string(),
@@ -235,14 +808,71 @@ fn handle_set_hhd_divert(request: &mut Request, divert_type: DivertType) -> Iron
     Ok(Response::with((content_type, status::Ok, json::encode(&response).unwrap())))
 }
 
-fn handle_reset_hhd(_request: &mut Request) -> IronResult<Response> {
-    println!("Reset HHD Table");
+fn handle_get_detections(_request: &mut Request) -> IronResult<Response> {
+    let result = DetectionManager::get_detections();
+    let content_type = Mime(TopLevel::Application, SubLevel::Json, Vec::new());
+    Ok(Response::with((content_type, status::Ok, json::encode(&result).unwrap())))
+}
+
+fn handle_get_detection_status(_request: &mut Request) -> IronResult<Response> {
+    let result = DetectionManager::get_status();
+    let content_type = Mime(TopLevel::Application, SubLevel::Json, Vec::new());
+    Ok(Response::with((content_type, status::Ok, json::encode(&result).unwrap())))
+}
 
-    HHDManager::reset_hhd();
+fn handle_set_detections(request: &mut Request) -> IronResult<Response> {
+    let mut body = String::new();
+    request.body.read_to_string(&mut body).unwrap();
+    let detections: Detections = match json::decode(&body) {
+        Ok(detections) => detections,
+        Err(err) => return respond_with_error(status::BadRequest, format!("invalid detections: {}", err)),
+    };
 
+    println!("Set {:?}", detections);
+
+    let mut settings = DetectionSettings::new(detections.superspreader_threshold, detections.ddos_target_threshold);
+    if let Some(interval_in_seconds) = detections.interval_in_seconds {
+        settings.interval_in_seconds = interval_in_seconds;
+    }
+    if let Some(window_in_seconds) = detections.window_in_seconds {
+        settings.window_in_seconds = window_in_seconds;
+    }
+    if let Some(ref name) = detections.action {
+        match DetectionAction::from_name(name) {
+            Some(action) => settings.action = action,
+            None => {
+                return respond_with_error(status::BadRequest, format!("unknown detection action {}", name));
+            }
+        }
+    }
+    settings.divert_egress = detections.divert_egress;
+    if let Some(max_number_of_actions) = detections.max_number_of_actions {
+        settings.max_number_of_actions = max_number_of_actions;
+    }
+
+    match DetectionManager::run(settings) {
+        Ok(()) => respond_with_done(),
+        Err(err) => respond_with_error(status::BadRequest, err),
+    }
+}
+
+fn handle_stop_detections(_request: &mut Request) -> IronResult<Response> {
+    println!("Stop Detections");
+
+    DetectionManager::stop();
+    respond_with_done()
+}
+
+fn respond_with_done() -> IronResult<Response> {
     let response = SimpleResponse {
         result: "done".to_string(),
     };
     let content_type = Mime(TopLevel::Application, SubLevel::Json, Vec::new());
     Ok(Response::with((content_type, status::Ok, json::encode(&response).unwrap())))
 }
+
+fn respond_with_error(status: status::Status, message: String) -> IronResult<Response> {
+    let response = SimpleResponse { result: message };
+    let content_type = Mime(TopLevel::Application, SubLevel::Json, Vec::new());
+    Ok(Response::with((content_type, status, json::encode(&response).unwrap())))
+}
@@ -173,4 +173,19 @@ pub mod BFLayer {
         }
         result
     }
+
+    pub fn get_port_oper_state(dev_port: i32) -> bool {
+        let result: bool;
+        unsafe {
+            let oper_state: *mut bool = malloc(mem::size_of::<bool>()) as *mut bool;
+            *oper_state = false;
+            let status = bf_pal_port_oper_state_get(0, dev_port, oper_state);
+            if status != 0 {
+                println!("get_port_oper_state error status {}", status);
+            };
+
+            result = *oper_state;
+        }
+        result
+    }
 }
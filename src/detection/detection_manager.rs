@@ -0,0 +1,503 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2018 AT&T. All other rights reserved.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+use event::{EventManager, EventType};
+use flows::{current_time_in_millis, Flow, FlowsManager};
+use l2::{DivertType, L2Manager};
+use label::LabelingManager;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DetectionAction {
+    Report,
+    Divert,
+    Drop,
+}
+
+impl DetectionAction {
+    pub fn from_name(name: &str) -> Option<DetectionAction> {
+        match name {
+            "report" => Some(DetectionAction::Report),
+            "divert" => Some(DetectionAction::Divert),
+            "drop" => Some(DetectionAction::Drop),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            DetectionAction::Report => "report",
+            DetectionAction::Divert => "divert",
+            DetectionAction::Drop => "drop",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DetectionKind {
+    // a source contacting many distinct destinations, e.g. a scanner
+    Superspreader,
+    // a destination contacted by many distinct sources, e.g. a DDoS victim
+    DDoSTarget,
+}
+
+impl DetectionKind {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            DetectionKind::Superspreader => "superspreader",
+            DetectionKind::DDoSTarget => "ddos-target",
+        }
+    }
+
+    // rules match the offending address as source or as destination
+    fn divert_type(&self) -> DivertType {
+        match *self {
+            DetectionKind::Superspreader => DivertType::IPSrc,
+            DetectionKind::DDoSTarget => DivertType::IPDest,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct DetectionSettings {
+    pub interval_in_seconds: u32,
+    pub window_in_seconds: u32,
+    pub superspreader_threshold: Option<usize>,
+    pub ddos_target_threshold: Option<usize>,
+    pub action: DetectionAction,
+    pub divert_egress: Option<u32>,
+    pub max_number_of_actions: usize,
+}
+
+impl DetectionSettings {
+    pub fn new(superspreader_threshold: Option<usize>, ddos_target_threshold: Option<usize>) -> DetectionSettings {
+        DetectionSettings {
+            interval_in_seconds: 10,
+            window_in_seconds: 60,
+            superspreader_threshold: superspreader_threshold,
+            ddos_target_threshold: ddos_target_threshold,
+            action: DetectionAction::Report,
+            divert_egress: None,
+            max_number_of_actions: 10,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.superspreader_threshold.is_none() && self.ddos_target_threshold.is_none() {
+            return Err("either superspreader_threshold or ddos_target_threshold has to be set".to_string());
+        }
+        if self.superspreader_threshold == Some(0) || self.ddos_target_threshold == Some(0) {
+            return Err("detection thresholds have to be at least 1".to_string());
+        }
+        if self.interval_in_seconds == 0 || self.window_in_seconds == 0 {
+            return Err("interval_in_seconds and window_in_seconds have to be at least 1".to_string());
+        }
+        if self.action == DetectionAction::Divert && self.divert_egress.is_none() {
+            return Err("divert_egress has to be set to divert offenders".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, RustcEncodable)]
+pub struct Detection {
+    pub kind: String,
+    pub address: String,
+    pub address_int: u32,
+    pub number_of_peers: usize,
+    pub number_of_flows: usize,
+    pub packets: u64,
+    pub bytes: u64,
+    pub ports_ingress: Vec<u32>,
+    pub timestamp: u64,
+    pub action: String,
+}
+
+#[derive(Clone, Debug, RustcEncodable)]
+pub struct DetectionRule {
+    pub kind: String,
+    pub address: String,
+    pub action: String,
+    pub port_ingress: u32,
+    pub port_egress: Option<u32>,
+    pub since: u64,
+}
+
+#[derive(Clone, Debug, RustcEncodable)]
+pub struct DetectionStatus {
+    pub running: bool,
+    pub interval_in_seconds: u32,
+    pub window_in_seconds: u32,
+    pub superspreader_threshold: Option<usize>,
+    pub ddos_target_threshold: Option<usize>,
+    pub action: String,
+    pub divert_egress: Option<u32>,
+    pub max_number_of_actions: usize,
+    pub last_run: Option<u64>,
+    pub number_of_detections: usize,
+    pub current: Vec<Detection>,
+    pub rules: Vec<DetectionRule>,
+}
+
+// a divert or drop rule installed for an offender on one ingress port
+struct InstalledRule {
+    kind: DetectionKind,
+    address: String,
+    address_int: u32,
+    action: DetectionAction,
+    port_ingress: u32,
+    port_egress: Option<u32>,
+    since: u64,
+    entry_handle: u32,
+}
+
+// peers and traffic of one address within the detection window
+struct PeerCount {
+    address: String,
+    address_int: u32,
+    peers: HashSet<u32>,
+    number_of_flows: usize,
+    packets: u64,
+    bytes: u64,
+    ports_ingress: Vec<u32>,
+}
+
+impl PeerCount {
+    fn new(address: &String, address_int: u32) -> PeerCount {
+        PeerCount {
+            address: address.clone(),
+            address_int: address_int,
+            peers: HashSet::new(),
+            number_of_flows: 0,
+            packets: 0,
+            bytes: 0,
+            ports_ingress: Vec::new(),
+        }
+    }
+
+    fn add(&mut self, peer: u32, packets: u64, bytes: u64, ports_ingress: &Vec<u32>) {
+        self.peers.insert(peer);
+        self.number_of_flows += 1;
+        self.packets += packets;
+        self.bytes += bytes;
+        for port_ingress in ports_ingress {
+            if !self.ports_ingress.contains(port_ingress) {
+                self.ports_ingress.push(*port_ingress);
+            }
+        }
+    }
+}
+
+pub struct DetectionManager {
+    settings: Option<DetectionSettings>,
+    // a new generation stops the detector thread of the previous one
+    generation: u64,
+    history_size: usize,
+    history: VecDeque<Detection>,
+    current: Vec<Detection>,
+    rules: Vec<InstalledRule>,
+    last_run: Option<u64>,
+}
+
+lazy_static! {
+    static ref MANAGER: Mutex<DetectionManager> = Mutex::new(
+        DetectionManager{
+            settings: None,
+            generation: 0,
+            history_size: 100,
+            history: VecDeque::new(),
+            current: Vec::new(),
+            rules: Vec::new(),
+            last_run: None,
+        });
+}
+
+impl DetectionManager {
+    pub fn init(history_size: usize) {
+        MANAGER.lock().unwrap().history_size = history_size;
+    }
+
+    // (re)starts the detector, rules of a previous run get removed first
+    pub fn run(settings: DetectionSettings) -> Result<(), String> {
+        settings.validate()?;
+
+        let generation = {
+            let mut manager = MANAGER.lock().unwrap();
+            manager.remove_rules();
+            manager.current.clear();
+            manager.generation += 1;
+            manager.settings = Some(settings.clone());
+            manager.generation
+        };
+
+        println!(
+            "Detections running every {}s over {}s, superspreader threshold {:?}, ddos target threshold {:?}, action {}",
+            settings.interval_in_seconds,
+            settings.window_in_seconds,
+            settings.superspreader_threshold,
+            settings.ddos_target_threshold,
+            settings.action.name()
+        );
+
+        let interval_in_seconds = settings.interval_in_seconds;
+        let _ = thread::Builder::new().name("detections".to_string()).spawn(move || loop {
+            thread::sleep(Duration::from_secs(interval_in_seconds as u64));
+            if !detect(generation) {
+                break;
+            }
+        });
+
+        Ok(())
+    }
+
+    pub fn stop() {
+        let mut manager = MANAGER.lock().unwrap();
+        manager.remove_rules();
+        manager.current.clear();
+        manager.generation += 1;
+        manager.settings = None;
+        println!("Detections stopped");
+    }
+
+    pub fn get_detections() -> Vec<Detection> {
+        MANAGER.lock().unwrap().history.iter().cloned().collect()
+    }
+
+    pub fn get_status() -> DetectionStatus {
+        let manager = MANAGER.lock().unwrap();
+        let settings = manager.settings.clone().unwrap_or(DetectionSettings::new(None, None));
+
+        DetectionStatus {
+            running: manager.settings.is_some(),
+            interval_in_seconds: settings.interval_in_seconds,
+            window_in_seconds: settings.window_in_seconds,
+            superspreader_threshold: settings.superspreader_threshold,
+            ddos_target_threshold: settings.ddos_target_threshold,
+            action: settings.action.name().to_string(),
+            divert_egress: settings.divert_egress,
+            max_number_of_actions: settings.max_number_of_actions,
+            last_run: manager.last_run,
+            number_of_detections: manager.history.len(),
+            current: manager.current.clone(),
+            rules: manager
+                .rules
+                .iter()
+                .map(|rule| DetectionRule {
+                    kind: rule.kind.name().to_string(),
+                    address: rule.address.clone(),
+                    action: rule.action.name().to_string(),
+                    port_ingress: rule.port_ingress,
+                    port_egress: rule.port_egress,
+                    since: rule.since,
+                })
+                .collect(),
+        }
+    }
+
+    fn remove_rules(&mut self) {
+        for rule in self.rules.drain(..) {
+            L2Manager::remove_divert_entry(
+                rule.entry_handle,
+                rule.kind.divert_type(),
+                rule.port_ingress,
+                rule.port_egress,
+                &rule.address,
+                32,
+            );
+        }
+    }
+
+    fn number_of_offenders_with_rules(&self) -> usize {
+        let offenders: HashSet<(DetectionKind, u32)> = self.rules.iter().map(|rule| (rule.kind, rule.address_int)).collect();
+        offenders.len()
+    }
+
+    fn has_rules(&self, kind: DetectionKind, address_int: u32) -> bool {
+        self.rules.iter().any(|rule| rule.kind == kind && rule.address_int == address_int)
+    }
+
+    // rules stay until the detector gets stopped or reconfigured, offenders are not let go on their own
+    fn apply_action(&mut self, settings: &DetectionSettings, kind: DetectionKind, peer_count: &PeerCount, now: u64) -> DetectionAction {
+        if settings.action == DetectionAction::Report {
+            return DetectionAction::Report;
+        }
+
+        let address_int = peer_count.address_int;
+        if !self.has_rules(kind, address_int) && self.number_of_offenders_with_rules() >= settings.max_number_of_actions {
+            println!("Detections, max number of actions reached, only reporting {} {}", kind.name(), peer_count.address);
+            return DetectionAction::Report;
+        }
+
+        for port_ingress in peer_count.ports_ingress.iter() {
+            let covered = self.rules
+                .iter()
+                .any(|rule| rule.kind == kind && rule.address_int == address_int && rule.port_ingress == *port_ingress);
+            if covered {
+                continue;
+            }
+
+            let entry_handle = match settings.action {
+                DetectionAction::Drop => L2Manager::set_drop(kind.divert_type(), *port_ingress, &peer_count.address, 32),
+                _ => L2Manager::set_divert(kind.divert_type(), *port_ingress, settings.divert_egress.unwrap(), &peer_count.address, 32, true),
+            };
+
+            self.rules.push(InstalledRule {
+                kind: kind,
+                address: peer_count.address.clone(),
+                address_int: address_int,
+                action: settings.action,
+                port_ingress: *port_ingress,
+                port_egress: match settings.action {
+                    DetectionAction::Drop => None,
+                    _ => settings.divert_egress,
+                },
+                since: now,
+                entry_handle: entry_handle,
+            });
+        }
+
+        settings.action
+    }
+}
+
+// returns false once the detector got stopped or reconfigured
+fn detect(generation: u64) -> bool {
+    let settings = {
+        let manager = MANAGER.lock().unwrap();
+        if manager.generation != generation {
+            return false;
+        }
+        manager.settings.clone().unwrap()
+    };
+
+    let now = current_time_in_millis();
+    let flows = FlowsManager::get_flows_active_between(now.saturating_sub(settings.window_in_seconds as u64 * 1000), now);
+
+    // a flow that aged out and got learned again is both in the history and learned, it only counts once
+    let mut unique_flows: HashMap<(u32, u16, u32, u16, u8), (Flow, Vec<u32>)> = HashMap::new();
+    for flow in flows {
+        let five_tuple = (flow.src_addr_int, flow.src_port, flow.dst_addr_int, flow.dst_port, flow.ipv4_protocol);
+        match unique_flows.entry(five_tuple) {
+            Entry::Occupied(mut entry) => {
+                let &mut (ref mut unique_flow, ref mut ports_ingress) = entry.get_mut();
+                unique_flow.packets += flow.packets;
+                unique_flow.bytes += flow.bytes;
+                if !ports_ingress.contains(&flow.ingress_port) {
+                    ports_ingress.push(flow.ingress_port);
+                }
+            }
+            Entry::Vacant(entry) => {
+                let ports_ingress = vec![flow.ingress_port];
+                entry.insert((flow, ports_ingress));
+            }
+        }
+    }
+
+    let mut destinations_by_source: HashMap<u32, PeerCount> = HashMap::new();
+    let mut sources_by_destination: HashMap<u32, PeerCount> = HashMap::new();
+    for &(ref flow, ref ports_ingress) in unique_flows.values() {
+        destinations_by_source
+            .entry(flow.src_addr_int)
+            .or_insert(PeerCount::new(&flow.src_addr, flow.src_addr_int))
+            .add(flow.dst_addr_int, flow.packets, flow.bytes, ports_ingress);
+        sources_by_destination
+            .entry(flow.dst_addr_int)
+            .or_insert(PeerCount::new(&flow.dst_addr, flow.dst_addr_int))
+            .add(flow.src_addr_int, flow.packets, flow.bytes, ports_ingress);
+    }
+
+    let mut offenders: Vec<(DetectionKind, &PeerCount)> = Vec::new();
+    if let Some(threshold) = settings.superspreader_threshold {
+        offenders.extend(
+            destinations_by_source
+                .values()
+                .filter(|peer_count| peer_count.peers.len() >= threshold)
+                .map(|peer_count| (DetectionKind::Superspreader, peer_count)),
+        );
+    }
+    if let Some(threshold) = settings.ddos_target_threshold {
+        offenders.extend(
+            sources_by_destination
+                .values()
+                .filter(|peer_count| peer_count.peers.len() >= threshold)
+                .map(|peer_count| (DetectionKind::DDoSTarget, peer_count)),
+        );
+    }
+    // the widest spread gets the rules first if there are more offenders than max_number_of_actions
+    offenders.sort_by(|a, b| b.1.peers.len().cmp(&a.1.peers.len()));
+
+    let mut manager = MANAGER.lock().unwrap();
+    if manager.generation != generation {
+        return false;
+    }
+
+    let previous: HashSet<(String, u32)> = manager
+        .current
+        .iter()
+        .map(|detection| (detection.kind.clone(), detection.address_int))
+        .collect();
+
+    let mut current = Vec::new();
+    for &(kind, peer_count) in offenders.iter() {
+        let action = manager.apply_action(&settings, kind, peer_count, now);
+        let mut ports_ingress = peer_count.ports_ingress.clone();
+        ports_ingress.sort();
+
+        let detection = Detection {
+            kind: kind.name().to_string(),
+            address: peer_count.address.clone(),
+            address_int: peer_count.address_int,
+            number_of_peers: peer_count.peers.len(),
+            number_of_flows: peer_count.number_of_flows,
+            packets: peer_count.packets,
+            bytes: peer_count.bytes,
+            ports_ingress: ports_ingress,
+            timestamp: now,
+            action: action.name().to_string(),
+        };
+
+        // offenders still active from the previous run are not reported again
+        if !previous.contains(&(detection.kind.clone(), detection.address_int)) {
+            println!(
+                "Detected {} {}, {} distinct peers, action {}",
+                detection.kind, detection.address, detection.number_of_peers, detection.action
+            );
+            LabelingManager::label_detection(kind.name(), detection.address.clone(), detection.number_of_peers, action.name());
+            EventManager::publish(EventType::Detection, &detection);
+
+            manager.history.push_back(detection.clone());
+            while manager.history.len() > manager.history_size {
+                manager.history.pop_front();
+            }
+        }
+
+        current.push(detection);
+    }
+
+    manager.current = current;
+    manager.last_run = Some(now);
+    true
+}
@@ -0,0 +1,126 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2018 AT&T. All other rights reserved.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+use flows::current_time_in_millis;
+use rustc_serialize::Encodable;
+use rustc_serialize::json;
+use std::sync::Mutex;
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+
+#[derive(Clone, Copy, Debug)]
+pub enum EventType {
+    FlowLearned,
+    FlowAged,
+    HHDWindow,
+    DivertAdded,
+    DivertRemoved,
+    PortStatus,
+    Detection,
+}
+
+impl EventType {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            EventType::FlowLearned => "flow-learned",
+            EventType::FlowAged => "flow-aged",
+            EventType::HHDWindow => "hhd-window",
+            EventType::DivertAdded => "divert-added",
+            EventType::DivertRemoved => "divert-removed",
+            EventType::PortStatus => "port-status",
+            EventType::Detection => "detection",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Event {
+    pub event_type: EventType,
+    pub timestamp: u64,
+    pub data: String,
+}
+
+impl Event {
+    // data is already JSON encoded
+    pub fn to_json(&self) -> String {
+        format!("{{\"type\":\"{}\",\"timestamp\":{},\"data\":{}}}", self.event_type.name(), self.timestamp, self.data)
+    }
+}
+
+// every subscriber holds an API worker thread, too many of them would starve the REST API
+pub const MAX_NUMBER_OF_SUBSCRIBERS: usize = 4;
+
+// events a subscriber can fall behind before it gets dropped
+const MAX_QUEUED_EVENTS: usize = 1000;
+
+pub struct EventManager {
+    subscribers: Vec<(u64, SyncSender<Event>)>,
+    next_subscriber_id: u64,
+}
+
+lazy_static! {
+    static ref MANAGER: Mutex<EventManager> = Mutex::new(EventManager{subscribers: Vec::new(), next_subscriber_id: 0});
+}
+
+impl EventManager {
+    // None if there are MAX_NUMBER_OF_SUBSCRIBERS already
+    pub fn subscribe() -> Option<(u64, Receiver<Event>)> {
+        let mut manager = MANAGER.lock().unwrap();
+        if manager.subscribers.len() >= MAX_NUMBER_OF_SUBSCRIBERS {
+            return None;
+        }
+
+        let (sender, receiver) = sync_channel(MAX_QUEUED_EVENTS);
+        manager.next_subscriber_id = manager.next_subscriber_id + 1;
+        let subscriber_id = manager.next_subscriber_id;
+        manager.subscribers.push((subscriber_id, sender));
+        println!("Event subscriber added, {} subscribers", manager.subscribers.len());
+        Some((subscriber_id, receiver))
+    }
+
+    pub fn unsubscribe(subscriber_id: u64) {
+        let mut manager = MANAGER.lock().unwrap();
+        manager.subscribers.retain(|&(id, _)| id != subscriber_id);
+        println!("Event subscriber removed, {} subscribers", manager.subscribers.len());
+    }
+
+    pub fn publish<T: Encodable>(event_type: EventType, data: &T) {
+        let mut manager = MANAGER.lock().unwrap();
+        if manager.subscribers.is_empty() {
+            return;
+        }
+
+        let event = Event {
+            event_type: event_type,
+            timestamp: current_time_in_millis(),
+            data: json::encode(data).unwrap(),
+        };
+
+        // subscribers that went away have dropped their receiver, slow ones with a full queue get dropped
+        manager.subscribers.retain(|&(id, ref subscriber)| match subscriber.try_send(event.clone()) {
+            Ok(()) => true,
+            Err(_) => {
+                println!("Event subscriber {} dropped", id);
+                false
+            }
+        });
+    }
+}
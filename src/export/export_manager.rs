@@ -0,0 +1,233 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2018 AT&T. All other rights reserved.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+use export::flow_encoder::{self, ExportRecord};
+use flows::{current_time_in_millis, Flow, FlowsManager};
+use rustc_serialize::{Encodable, Encoder};
+use std::collections::HashMap;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExportProtocol {
+    Ipfix,
+    NetflowV9,
+}
+
+impl ExportProtocol {
+    pub fn from_name(name: &str) -> Option<ExportProtocol> {
+        match name {
+            "ipfix" => Some(ExportProtocol::Ipfix),
+            "netflow-v9" => Some(ExportProtocol::NetflowV9),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            ExportProtocol::Ipfix => "ipfix",
+            ExportProtocol::NetflowV9 => "netflow-v9",
+        }
+    }
+}
+
+impl Encodable for ExportProtocol {
+    fn encode<S: Encoder>(&self, encoder: &mut S) -> Result<(), S::Error> {
+        encoder.emit_str(self.name())
+    }
+}
+
+#[derive(Clone, RustcEncodable)]
+#[allow(non_snake_case)]
+pub struct Collector {
+    pub Address: String,
+    pub Protocol: ExportProtocol,
+}
+
+struct CollectorState {
+    collector: Collector,
+    address: SocketAddr,
+    sequence_number: u32,
+    last_template_sent: Option<u64>,
+}
+
+pub struct ExportManager {
+    collectors: Vec<CollectorState>,
+    exported_counters: HashMap<Flow, (u64, u64)>,
+    template_refresh_in_seconds: u32,
+    observation_domain_id: u32,
+    system_start: u64,
+}
+
+lazy_static! {
+    static ref MANAGER: Mutex<ExportManager> = Mutex::new(
+        ExportManager{
+            collectors: Vec::new(),
+            exported_counters: HashMap::new(),
+            template_refresh_in_seconds: 300,
+            observation_domain_id: 0,
+            system_start: 0,
+        });
+}
+
+impl ExportManager {
+    pub fn run(collectors: Vec<Collector>, export_interval_in_seconds: u16, template_refresh_in_seconds: u32, observation_domain_id: u32) {
+        if collectors.is_empty() {
+            println!("Flow Export turned off, no collectors configured");
+            return;
+        }
+
+        let socket = match UdpSocket::bind("0.0.0.0:0") {
+            Ok(socket) => socket,
+            Err(err) => {
+                println!("Flow Export turned off, can't open socket: {}", err);
+                return;
+            }
+        };
+
+        {
+            let mut manager = MANAGER.lock().unwrap();
+            manager.template_refresh_in_seconds = template_refresh_in_seconds;
+            manager.observation_domain_id = observation_domain_id;
+            manager.system_start = current_time_in_millis();
+            FlowsManager::keep_removed_flows();
+
+            for collector in collectors {
+                match resolve_address(&collector.Address) {
+                    Some(address) => {
+                        println!("Flow Export to {} using {}", address, collector.Protocol.name());
+                        manager.collectors.push(CollectorState {
+                            collector: collector,
+                            address: address,
+                            sequence_number: 0,
+                            last_template_sent: None,
+                        });
+                    }
+                    None => println!("Flow Export, can't resolve collector {}", collector.Address),
+                }
+            }
+        }
+
+        let _ = thread::Builder::new().name("flows-exporter".to_string()).spawn(move || loop {
+            thread::sleep(Duration::from_secs(export_interval_in_seconds as u64));
+            export_flows(&socket);
+        });
+    }
+}
+
+fn resolve_address(address: &String) -> Option<SocketAddr> {
+    match address.to_socket_addrs() {
+        Ok(mut addresses) => addresses.next(),
+        Err(_) => None,
+    }
+}
+
+// the delta of a flow since its exported packet and byte counters, none if it didn't count any packets since
+fn create_record(flow: Flow, exported: Option<(u64, u64)>) -> Option<ExportRecord> {
+    let (packet_delta, byte_delta) = match exported {
+        Some((packets, bytes)) if flow.packets > packets => (flow.packets - packets, flow.bytes.saturating_sub(bytes)),
+        Some(_) => return None,
+        None if flow.packets > 0 => (flow.packets, flow.bytes),
+        None => return None,
+    };
+
+    Some(ExportRecord {
+        flow: flow,
+        packet_delta: packet_delta,
+        byte_delta: byte_delta,
+    })
+}
+
+fn export_flows(socket: &UdpSocket) {
+    let (learned_flows, removed_flows) = FlowsManager::get_learned_and_removed_flows();
+    let mut manager = MANAGER.lock().unwrap();
+
+    // only export what changed since the last export, removed flows go out with what they counted since then
+    let mut records = Vec::new();
+    for flow in removed_flows {
+        let exported = manager.exported_counters.remove(&flow);
+        records.extend(create_record(flow, exported));
+    }
+
+    let mut exported_counters = HashMap::with_capacity(learned_flows.len());
+    for flow in learned_flows {
+        let exported = manager.exported_counters.get(&flow).cloned();
+        exported_counters.insert(flow.clone(), (flow.packets, flow.bytes));
+        records.extend(create_record(flow, exported));
+    }
+    manager.exported_counters = exported_counters;
+
+    if records.is_empty() {
+        return;
+    }
+
+    let now = current_time_in_millis();
+    let template_refresh = manager.template_refresh_in_seconds as u64 * 1000;
+    let observation_domain_id = manager.observation_domain_id;
+    let system_start = manager.system_start;
+
+    for state in manager.collectors.iter_mut() {
+        for chunk in records.chunks(flow_encoder::MAX_RECORDS_PER_MESSAGE) {
+            let with_template = match state.last_template_sent {
+                Some(last_template_sent) => now.saturating_sub(last_template_sent) >= template_refresh,
+                None => true,
+            };
+
+            let message = match state.collector.Protocol {
+                ExportProtocol::NetflowV9 => {
+                    let message = flow_encoder::encode_netflow_v9_message(
+                        chunk,
+                        with_template,
+                        system_start,
+                        now,
+                        state.sequence_number,
+                        observation_domain_id,
+                    );
+                    state.sequence_number = state.sequence_number.wrapping_add(1);
+                    message
+                }
+                ExportProtocol::Ipfix => {
+                    let message = flow_encoder::encode_ipfix_message(
+                        chunk,
+                        with_template,
+                        (now / 1000) as u32,
+                        state.sequence_number,
+                        observation_domain_id,
+                    );
+                    state.sequence_number = state.sequence_number.wrapping_add(chunk.len() as u32);
+                    message
+                }
+            };
+
+            match socket.send_to(&message, state.address) {
+                Ok(_) => {
+                    if with_template {
+                        state.last_template_sent = Some(now);
+                    }
+                }
+                Err(err) => println!("Flow Export to {} failed: {}", state.address, err),
+            }
+        }
+    }
+}
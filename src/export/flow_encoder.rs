@@ -0,0 +1,201 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2018 AT&T. All other rights reserved.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+use flows::Flow;
+
+const IPFIX_VERSION: u16 = 10;
+const IPFIX_TEMPLATE_SET_ID: u16 = 2;
+const NETFLOW_V9_VERSION: u16 = 9;
+const NETFLOW_V9_TEMPLATE_FLOWSET_ID: u16 = 0;
+pub const TEMPLATE_ID: u16 = 256;
+
+// keep messages within the 1472 bytes of UDP payload of a 1500 byte MTU, an IPFIX message with template
+// takes 16 bytes of header, 48 of template and 4 of set header plus 49 per record, 1440 bytes for 28 records,
+// NetFlow v9 records are smaller
+pub const MAX_RECORDS_PER_MESSAGE: usize = 28;
+
+// (information element id, length in bytes)
+const IPFIX_TEMPLATE: [(u16, u16); 10] = [
+    (8, 4),   // sourceIPv4Address
+    (12, 4),  // destinationIPv4Address
+    (4, 1),   // protocolIdentifier
+    (7, 2),   // sourceTransportPort
+    (11, 2),  // destinationTransportPort
+    (10, 4),  // ingressInterface
+    (2, 8),   // packetDeltaCount
+    (1, 8),   // octetDeltaCount
+    (152, 8), // flowStartMilliseconds
+    (153, 8), // flowEndMilliseconds
+];
+
+// (field type, length in bytes)
+const NETFLOW_V9_TEMPLATE: [(u16, u16); 10] = [
+    (8, 4),  // IPV4_SRC_ADDR
+    (12, 4), // IPV4_DST_ADDR
+    (4, 1),  // PROTOCOL
+    (7, 2),  // L4_SRC_PORT
+    (11, 2), // L4_DST_PORT
+    (10, 4), // INPUT_SNMP
+    (2, 8),  // IN_PKTS
+    (1, 8),  // IN_BYTES
+    (22, 4), // FIRST_SWITCHED
+    (21, 4), // LAST_SWITCHED
+];
+
+pub struct ExportRecord {
+    pub flow: Flow,
+    pub packet_delta: u64,
+    pub byte_delta: u64,
+}
+
+pub fn encode_ipfix_message(
+    records: &[ExportRecord],
+    with_template: bool,
+    export_time_in_seconds: u32,
+    sequence_number: u32,
+    observation_domain_id: u32,
+) -> Vec<u8> {
+    let mut message = Vec::with_capacity(1500);
+
+    // message header, length gets patched at the end
+    push_u16(&mut message, IPFIX_VERSION);
+    push_u16(&mut message, 0);
+    push_u32(&mut message, export_time_in_seconds);
+    push_u32(&mut message, sequence_number);
+    push_u32(&mut message, observation_domain_id);
+
+    if with_template {
+        push_u16(&mut message, IPFIX_TEMPLATE_SET_ID);
+        push_u16(&mut message, (4 + 4 + IPFIX_TEMPLATE.len() * 4) as u16);
+        push_u16(&mut message, TEMPLATE_ID);
+        push_u16(&mut message, IPFIX_TEMPLATE.len() as u16);
+        for &(id, length) in IPFIX_TEMPLATE.iter() {
+            push_u16(&mut message, id);
+            push_u16(&mut message, length);
+        }
+    }
+
+    if !records.is_empty() {
+        let set_start = message.len();
+        push_u16(&mut message, TEMPLATE_ID);
+        push_u16(&mut message, 0);
+        for record in records {
+            push_u32(&mut message, record.flow.src_addr_int);
+            push_u32(&mut message, record.flow.dst_addr_int);
+            message.push(record.flow.ipv4_protocol);
+            push_u16(&mut message, record.flow.src_port);
+            push_u16(&mut message, record.flow.dst_port);
+            push_u32(&mut message, record.flow.ingress_port);
+            push_u64(&mut message, record.packet_delta);
+            push_u64(&mut message, record.byte_delta);
+            push_u64(&mut message, record.flow.first_seen);
+            push_u64(&mut message, record.flow.last_seen);
+        }
+        let set_length = message.len() - set_start;
+        patch_u16(&mut message, set_start + 2, set_length as u16);
+    }
+
+    let message_length = message.len();
+    patch_u16(&mut message, 2, message_length as u16);
+    message
+}
+
+pub fn encode_netflow_v9_message(
+    records: &[ExportRecord],
+    with_template: bool,
+    system_start_in_millis: u64,
+    export_time_in_millis: u64,
+    sequence_number: u32,
+    source_id: u32,
+) -> Vec<u8> {
+    let mut message = Vec::with_capacity(1500);
+    let record_count = records.len() + if with_template { 1 } else { 0 };
+
+    push_u16(&mut message, NETFLOW_V9_VERSION);
+    push_u16(&mut message, record_count as u16);
+    push_u32(&mut message, uptime(system_start_in_millis, export_time_in_millis));
+    push_u32(&mut message, (export_time_in_millis / 1000) as u32);
+    push_u32(&mut message, sequence_number);
+    push_u32(&mut message, source_id);
+
+    if with_template {
+        push_u16(&mut message, NETFLOW_V9_TEMPLATE_FLOWSET_ID);
+        push_u16(&mut message, (4 + 4 + NETFLOW_V9_TEMPLATE.len() * 4) as u16);
+        push_u16(&mut message, TEMPLATE_ID);
+        push_u16(&mut message, NETFLOW_V9_TEMPLATE.len() as u16);
+        for &(id, length) in NETFLOW_V9_TEMPLATE.iter() {
+            push_u16(&mut message, id);
+            push_u16(&mut message, length);
+        }
+    }
+
+    if !records.is_empty() {
+        let flowset_start = message.len();
+        push_u16(&mut message, TEMPLATE_ID);
+        push_u16(&mut message, 0);
+        for record in records {
+            push_u32(&mut message, record.flow.src_addr_int);
+            push_u32(&mut message, record.flow.dst_addr_int);
+            message.push(record.flow.ipv4_protocol);
+            push_u16(&mut message, record.flow.src_port);
+            push_u16(&mut message, record.flow.dst_port);
+            push_u32(&mut message, record.flow.ingress_port);
+            push_u64(&mut message, record.packet_delta);
+            push_u64(&mut message, record.byte_delta);
+            push_u32(&mut message, uptime(system_start_in_millis, record.flow.first_seen));
+            push_u32(&mut message, uptime(system_start_in_millis, record.flow.last_seen));
+        }
+
+        // flowsets are padded to a 32 bit boundary
+        while (message.len() - flowset_start) % 4 != 0 {
+            message.push(0);
+        }
+        let flowset_length = message.len() - flowset_start;
+        patch_u16(&mut message, flowset_start + 2, flowset_length as u16);
+    }
+
+    message
+}
+
+fn uptime(system_start_in_millis: u64, time_in_millis: u64) -> u32 {
+    time_in_millis.saturating_sub(system_start_in_millis) as u32
+}
+
+fn push_u16(buffer: &mut Vec<u8>, value: u16) {
+    buffer.push((value >> 8) as u8);
+    buffer.push(value as u8);
+}
+
+fn push_u32(buffer: &mut Vec<u8>, value: u32) {
+    push_u16(buffer, (value >> 16) as u16);
+    push_u16(buffer, value as u16);
+}
+
+fn push_u64(buffer: &mut Vec<u8>, value: u64) {
+    push_u32(buffer, (value >> 32) as u32);
+    push_u32(buffer, value as u32);
+}
+
+fn patch_u16(buffer: &mut Vec<u8>, position: usize, value: u16) {
+    buffer[position] = (value >> 8) as u8;
+    buffer[position + 1] = value as u8;
+}
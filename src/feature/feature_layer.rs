@@ -0,0 +1,110 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2018 AT&T. All other rights reserved.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+#![allow(non_upper_case_globals)]
+#![allow(non_camel_case_types)]
+#![allow(non_snake_case)]
+#![allow(dead_code)]
+
+pub mod FeatureLayer {
+    include!("../../gen-stub/bindings-taep.rs");
+
+    use std::mem;
+
+    pub fn init() -> u32 {
+        unsafe {
+            let session_handler: *mut p4_pd_sess_hdl_t = malloc(mem::size_of::<p4_pd_sess_hdl_t>()) as *mut p4_pd_sess_hdl_t;
+            let _status = p4_pd_client_init(session_handler);
+
+            *session_handler
+        }
+    }
+
+    pub fn add_feature_entry(session_handler: u32, dev_port_ingress: u16, hhd: bool, flows: bool) -> u32 {
+        unsafe {
+            let match_spec: *mut p4_pd_l2_switching_feature_match_spec_t =
+                malloc(mem::size_of::<p4_pd_l2_switching_feature_match_spec_t>()) as *mut p4_pd_l2_switching_feature_match_spec_t;
+            let action_spec: *mut p4_pd_l2_switching_feature_enable_action_spec_t =
+                malloc(mem::size_of::<p4_pd_l2_switching_feature_enable_action_spec_t>())
+                    as *mut p4_pd_l2_switching_feature_enable_action_spec_t;
+            let entry_hdl: *mut p4_pd_entry_hdl_t = malloc(mem::size_of::<p4_pd_entry_hdl_t>()) as *mut p4_pd_entry_hdl_t;
+
+            (*match_spec).ig_intr_md_ingress_port = dev_port_ingress;
+            (*action_spec).action_hhd = hhd as u8;
+            (*action_spec).action_flows = flows as u8;
+
+            p4_pd_l2_switching_feature_table_add_with_feature_enable(
+                session_handler,
+                resolve_dev_target(),
+                match_spec,
+                action_spec,
+                entry_hdl,
+            );
+            println!("Added Feature Setting hhd: {} flows: {}, Handle {}", hhd, flows, *entry_hdl);
+
+            *entry_hdl
+        }
+    }
+
+    pub fn modify_feature_entry(session_handler: u32, handle: u32, hhd: bool, flows: bool) {
+        unsafe {
+            let action_spec: *mut p4_pd_l2_switching_feature_enable_action_spec_t =
+                malloc(mem::size_of::<p4_pd_l2_switching_feature_enable_action_spec_t>())
+                    as *mut p4_pd_l2_switching_feature_enable_action_spec_t;
+
+            (*action_spec).action_hhd = hhd as u8;
+            (*action_spec).action_flows = flows as u8;
+
+            p4_pd_l2_switching_feature_table_modify_with_feature_enable(session_handler, 0 as u8, handle as p4_pd_entry_hdl_t, action_spec);
+            println!("Modified Feature Setting hhd: {} flows: {}, Handle {}", hhd, flows, handle);
+        }
+    }
+
+    pub fn delete_feature_entry(session_handler: u32, handle: u32) {
+        unsafe {
+            p4_pd_l2_switching_feature_table_delete(session_handler, 0 as u8, handle as p4_pd_entry_hdl_t);
+            println!("Delete Feature Setting, Handle {}", handle);
+        }
+    }
+
+    pub fn reset_feature_table(session_handler: u32) {
+        unsafe {
+            let mut status: p4_pd_status_t = 0;
+            while status == 0 {
+                let index: *mut i32 = malloc(mem::size_of::<i32>()) as *mut i32;
+                status = p4_pd_l2_switching_feature_get_first_entry_handle(session_handler, resolve_dev_target(), index);
+
+                if status == 0 {
+                    p4_pd_l2_switching_feature_table_delete(session_handler, 0 as u8, *index as p4_pd_entry_hdl_t);
+                    println!("Delete Feature Setting, Handle {}", *index);
+                }
+            }
+        }
+    }
+
+    fn resolve_dev_target() -> p4_pd_dev_target_t {
+        p4_pd_dev_target_t {
+            device_id: 0 as i32,
+            dev_pipe_id: DEV_PIPE_ALL as u16,
+        }
+    }
+}
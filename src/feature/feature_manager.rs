@@ -0,0 +1,125 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2018 AT&T. All other rights reserved.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+use feature::FeatureLayer;
+use hw::HWManager;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Feature {
+    HHD,
+    Flows,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct FeatureEntry {
+    handle: u32,
+    hhd: bool,
+    flows: bool,
+}
+
+// HHD and Flows share one entry per ingress port in the feature table
+pub struct FeatureManager {
+    session_handler: u32,
+    entries: HashMap<u32, FeatureEntry>,
+}
+
+lazy_static! {
+    static ref MANAGER: Mutex<FeatureManager> = Mutex::new(FeatureManager{session_handler: 0, entries: HashMap::new()});
+}
+
+impl FeatureManager {
+    pub fn init() {
+        let mut manager = MANAGER.lock().unwrap();
+        manager.session_handler = FeatureLayer::init();
+        FeatureLayer::reset_feature_table(manager.session_handler);
+    }
+
+    pub fn enable(feature: Feature, chassis_port_ingress: u32) {
+        FeatureManager::set(feature, chassis_port_ingress, true);
+    }
+
+    pub fn disable(feature: Feature, chassis_port_ingress: u32) {
+        FeatureManager::set(feature, chassis_port_ingress, false);
+    }
+
+    pub fn is_enabled(feature: Feature, chassis_port_ingress: u32) -> bool {
+        match MANAGER.lock().unwrap().entries.get(&chassis_port_ingress) {
+            Some(entry) => match feature {
+                Feature::HHD => entry.hhd,
+                Feature::Flows => entry.flows,
+            },
+            None => false,
+        }
+    }
+
+    fn set(feature: Feature, chassis_port_ingress: u32, enabled: bool) {
+        let mut manager = MANAGER.lock().unwrap();
+        let session_handler = manager.session_handler;
+
+        let current = manager.entries.get(&chassis_port_ingress).cloned();
+        let (hhd, flows) = {
+            let (hhd, flows) = match current {
+                Some(entry) => (entry.hhd, entry.flows),
+                None => (false, false),
+            };
+            match feature {
+                Feature::HHD => (enabled, flows),
+                Feature::Flows => (hhd, enabled),
+            }
+        };
+
+        match current {
+            Some(entry) => {
+                if !hhd && !flows {
+                    FeatureLayer::delete_feature_entry(session_handler, entry.handle);
+                    manager.entries.remove(&chassis_port_ingress);
+                } else if hhd != entry.hhd || flows != entry.flows {
+                    FeatureLayer::modify_feature_entry(session_handler, entry.handle, hhd, flows);
+                    manager.entries.insert(
+                        chassis_port_ingress,
+                        FeatureEntry {
+                            handle: entry.handle,
+                            hhd: hhd,
+                            flows: flows,
+                        },
+                    );
+                }
+            }
+            None => {
+                if hhd || flows {
+                    let dev_port_ingress = HWManager::convert_chassis_port_to_dev_port(&chassis_port_ingress);
+                    let handle = FeatureLayer::add_feature_entry(session_handler, dev_port_ingress as u16, hhd, flows);
+                    manager.entries.insert(
+                        chassis_port_ingress,
+                        FeatureEntry {
+                            handle: handle,
+                            hhd: hhd,
+                            flows: flows,
+                        },
+                    );
+                }
+            }
+        }
+    }
+}
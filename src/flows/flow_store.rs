@@ -0,0 +1,371 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2018 AT&T. All other rights reserved.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+use flows::Flow;
+use std::collections::{BTreeSet, HashMap};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FlowsEvictionPolicy {
+    DropNew,
+    LeastRecentlyUsed,
+    SmallestCounter,
+}
+
+impl FlowsEvictionPolicy {
+    pub fn from_name(name: &str) -> Option<FlowsEvictionPolicy> {
+        match name {
+            "drop-new" => Some(FlowsEvictionPolicy::DropNew),
+            "lru" => Some(FlowsEvictionPolicy::LeastRecentlyUsed),
+            "evict-smallest" => Some(FlowsEvictionPolicy::SmallestCounter),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            FlowsEvictionPolicy::DropNew => "drop-new",
+            FlowsEvictionPolicy::LeastRecentlyUsed => "lru",
+            FlowsEvictionPolicy::SmallestCounter => "evict-smallest",
+        }
+    }
+}
+
+// the counter evict-smallest ranks the flows by
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FlowsCounter {
+    Packets,
+    Bytes,
+}
+
+impl FlowsCounter {
+    fn of(&self, flow: &Flow) -> u64 {
+        match *self {
+            FlowsCounter::Packets => flow.packets,
+            FlowsCounter::Bytes => flow.bytes,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct FlowTuple {
+    src_addr: u32,
+    dst_addr: u32,
+    src_port: u16,
+    dst_port: u16,
+    ipv4_protocol: u8,
+    ingress_port: u32,
+}
+
+impl FlowTuple {
+    fn from_flow(flow: &Flow) -> FlowTuple {
+        FlowTuple {
+            src_addr: flow.src_addr_int,
+            dst_addr: flow.dst_addr_int,
+            src_port: flow.src_port,
+            dst_port: flow.dst_port,
+            ipv4_protocol: flow.ipv4_protocol,
+            ingress_port: flow.ingress_port,
+        }
+    }
+}
+
+struct FlowEntry {
+    flow: Flow,
+    previous: Option<usize>,
+    next: Option<usize>,
+}
+
+// flows are kept in slots, indexed by five-tuple and sketch hashes, and linked from least to most recently used
+pub struct FlowStore {
+    capacity: usize,
+    eviction_policy: FlowsEvictionPolicy,
+    eviction_counter: FlowsCounter,
+    eviction_grace_period_in_millis: u64,
+    slots: Vec<Option<FlowEntry>>,
+    free_slots: Vec<usize>,
+    by_tuple: HashMap<FlowTuple, usize>,
+    by_sketch_hash: HashMap<(usize, u16), Vec<usize>>,
+    by_counter: BTreeSet<(u64, usize)>,
+    least_recently_used: Option<usize>,
+    most_recently_used: Option<usize>,
+    number_of_dropped_flows: u64,
+    number_of_evicted_flows: u64,
+}
+
+impl FlowStore {
+    pub fn new(capacity: usize, eviction_policy: FlowsEvictionPolicy, eviction_grace_period_in_millis: u64) -> FlowStore {
+        FlowStore {
+            capacity: capacity,
+            eviction_policy: eviction_policy,
+            eviction_counter: FlowsCounter::Packets,
+            eviction_grace_period_in_millis: eviction_grace_period_in_millis,
+            slots: Vec::new(),
+            free_slots: Vec::new(),
+            by_tuple: HashMap::new(),
+            by_sketch_hash: HashMap::new(),
+            by_counter: BTreeSet::new(),
+            least_recently_used: None,
+            most_recently_used: None,
+            number_of_dropped_flows: 0,
+            number_of_evicted_flows: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_tuple.len()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn eviction_policy(&self) -> FlowsEvictionPolicy {
+        self.eviction_policy
+    }
+
+    pub fn set_eviction_counter(&mut self, eviction_counter: FlowsCounter) {
+        if self.eviction_counter == eviction_counter {
+            return;
+        }
+
+        self.eviction_counter = eviction_counter;
+        self.by_counter = self
+            .slots
+            .iter()
+            .enumerate()
+            .filter_map(|(slot, entry)| entry.as_ref().map(|entry| (eviction_counter.of(&entry.flow), slot)))
+            .collect();
+    }
+
+    pub fn number_of_dropped_flows(&self) -> u64 {
+        self.number_of_dropped_flows
+    }
+
+    pub fn number_of_evicted_flows(&self) -> u64 {
+        self.number_of_evicted_flows
+    }
+
+    pub fn get(&self, flow: &Flow) -> Option<&Flow> {
+        match self.by_tuple.get(&FlowTuple::from_flow(flow)) {
+            Some(slot) => Some(&self.entry(*slot).flow),
+            None => None,
+        }
+    }
+
+    pub fn number_of_flows_with_sketch_hash(&self, row: usize, hash: u16) -> usize {
+        self.by_sketch_hash.get(&(row, hash)).map(|slots| slots.len()).unwrap_or(0)
+    }
+
+    pub fn to_vec(&self) -> Vec<Flow> {
+        self.slots.iter().filter_map(|entry| entry.as_ref()).map(|entry| entry.flow.clone()).collect()
+    }
+
+    pub fn drain(&mut self) -> Vec<Flow> {
+        let flows = self.slots.drain(..).filter_map(|entry| entry).map(|entry| entry.flow).collect();
+        self.free_slots.clear();
+        self.by_tuple.clear();
+        self.by_sketch_hash.clear();
+        self.by_counter.clear();
+        self.least_recently_used = None;
+        self.most_recently_used = None;
+        flows
+    }
+
+    // adds a new flow, returns the flow that got evicted to make room or the new flow if it got dropped
+    pub fn insert(&mut self, flow: Flow) -> Result<Option<Flow>, Flow> {
+        if self.capacity == 0 {
+            self.number_of_dropped_flows = self.number_of_dropped_flows + 1;
+            return Err(flow);
+        }
+
+        let mut evicted_flow = None;
+        if self.len() >= self.capacity {
+            let victim = match self.eviction_policy {
+                FlowsEvictionPolicy::DropNew => None,
+                FlowsEvictionPolicy::LeastRecentlyUsed => self.least_recently_used,
+                FlowsEvictionPolicy::SmallestCounter => self.smallest_counter_past_grace_period(flow.first_seen),
+            };
+            match victim {
+                Some(slot) => {
+                    evicted_flow = Some(self.remove_slot(slot));
+                    self.number_of_evicted_flows = self.number_of_evicted_flows + 1;
+                }
+                None => {
+                    self.number_of_dropped_flows = self.number_of_dropped_flows + 1;
+                    return Err(flow);
+                }
+            }
+        }
+
+        let slot = match self.free_slots.pop() {
+            Some(slot) => slot,
+            None => {
+                self.slots.push(None);
+                self.slots.len() - 1
+            }
+        };
+
+        self.by_tuple.insert(FlowTuple::from_flow(&flow), slot);
+        for (row, hash) in flow.sketch_hashes.iter().enumerate() {
+            self.by_sketch_hash.entry((row, *hash)).or_insert(Vec::new()).push(slot);
+        }
+        self.by_counter.insert((self.eviction_counter.of(&flow), slot));
+        self.slots[slot] = Some(FlowEntry {
+            flow: flow,
+            previous: None,
+            next: None,
+        });
+        self.link_as_most_recently_used(slot);
+
+        Ok(evicted_flow)
+    }
+
+    pub fn remove(&mut self, flow: &Flow) -> Option<Flow> {
+        match self.by_tuple.get(&FlowTuple::from_flow(flow)).cloned() {
+            Some(slot) => Some(self.remove_slot(slot)),
+            None => None,
+        }
+    }
+
+    // marks the flow as seen, returns false if the flow isn't stored
+    pub fn touch(&mut self, flow: &Flow, last_seen: u64) -> bool {
+        match self.by_tuple.get(&FlowTuple::from_flow(flow)).cloned() {
+            Some(slot) => {
+                self.entry_mut(slot).flow.last_seen = last_seen;
+                self.unlink(slot);
+                self.link_as_most_recently_used(slot);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn add_counters(&mut self, flow: &Flow, packets: u64, bytes: u64, last_seen: u64) {
+        let slot = match self.by_tuple.get(&FlowTuple::from_flow(flow)).cloned() {
+            Some(slot) => slot,
+            None => return,
+        };
+
+        let eviction_counter = self.eviction_counter;
+        let previous_counter = eviction_counter.of(&self.entry(slot).flow);
+        self.by_counter.remove(&(previous_counter, slot));
+        let counter = {
+            let stored_flow = &mut self.entry_mut(slot).flow;
+            stored_flow.packets = stored_flow.packets + packets;
+            stored_flow.bytes = stored_flow.bytes + bytes;
+            eviction_counter.of(stored_flow)
+        };
+        self.by_counter.insert((counter, slot));
+        self.touch(flow, last_seen);
+    }
+
+    // least recently used flows come first, so only idle flows get visited
+    pub fn remove_idle(&mut self, now: u64, idle_timeout: u64) -> Vec<Flow> {
+        let mut idle_flows = Vec::new();
+        loop {
+            let slot = match self.least_recently_used {
+                Some(slot) if now.saturating_sub(self.entry(slot).flow.last_seen) > idle_timeout => slot,
+                _ => break,
+            };
+            idle_flows.push(self.remove_slot(slot));
+        }
+        idle_flows
+    }
+
+    // new flows got no counts yet, they only compete once they had the grace period to get counted
+    fn smallest_counter_past_grace_period(&self, now: u64) -> Option<usize> {
+        self.by_counter
+            .iter()
+            .map(|&(_, slot)| slot)
+            .find(|slot| now.saturating_sub(self.entry(*slot).flow.first_seen) >= self.eviction_grace_period_in_millis)
+    }
+
+    fn entry(&self, slot: usize) -> &FlowEntry {
+        self.slots[slot].as_ref().unwrap()
+    }
+
+    fn entry_mut(&mut self, slot: usize) -> &mut FlowEntry {
+        self.slots[slot].as_mut().unwrap()
+    }
+
+    fn remove_slot(&mut self, slot: usize) -> Flow {
+        self.unlink(slot);
+        let entry = self.slots[slot].take().unwrap();
+        self.free_slots.push(slot);
+
+        self.by_tuple.remove(&FlowTuple::from_flow(&entry.flow));
+        for (row, hash) in entry.flow.sketch_hashes.iter().enumerate() {
+            remove_from_bucket(&mut self.by_sketch_hash, (row, *hash), slot);
+        }
+        self.by_counter.remove(&(self.eviction_counter.of(&entry.flow), slot));
+
+        entry.flow
+    }
+
+    fn unlink(&mut self, slot: usize) {
+        let (previous, next) = {
+            let entry = self.entry(slot);
+            (entry.previous, entry.next)
+        };
+
+        match previous {
+            Some(previous) => self.entry_mut(previous).next = next,
+            None => self.least_recently_used = next,
+        }
+        match next {
+            Some(next) => self.entry_mut(next).previous = previous,
+            None => self.most_recently_used = previous,
+        }
+
+        let entry = self.entry_mut(slot);
+        entry.previous = None;
+        entry.next = None;
+    }
+
+    fn link_as_most_recently_used(&mut self, slot: usize) {
+        let most_recently_used = self.most_recently_used;
+        {
+            let entry = self.entry_mut(slot);
+            entry.previous = most_recently_used;
+            entry.next = None;
+        }
+
+        match most_recently_used {
+            Some(most_recently_used) => self.entry_mut(most_recently_used).next = Some(slot),
+            None => self.least_recently_used = Some(slot),
+        }
+        self.most_recently_used = Some(slot);
+    }
+}
+
+fn remove_from_bucket(buckets: &mut HashMap<(usize, u16), Vec<usize>>, hash: (usize, u16), slot: usize) {
+    let is_empty = match buckets.get_mut(&hash) {
+        Some(slots) => {
+            slots.retain(|other_slot| *other_slot != slot);
+            slots.is_empty()
+        }
+        None => false,
+    };
+    if is_empty {
+        buckets.remove(&hash);
+    }
+}
@@ -28,7 +28,8 @@
 pub mod FlowsLayer {
     include!("../../gen-stub/bindings-taep.rs");
 
-    use flows::{Flow, FlowsManager};
+    use flows::{current_time_in_millis, Flow, FlowCollision, FlowsManager};
+    use hw::HWManager;
     use std::mem;
     use std::net::Ipv4Addr;
     use std::os::raw::c_void;
@@ -51,6 +52,7 @@ pub mod FlowsLayer {
 
             (*match_spec).tcp_valid = 1;
             (*match_spec).udp_valid = 0;
+            (*match_spec).icmp_valid = 0;
 
             p4_pd_l2_switching_extract_flows_ports_table_add_with_get_flows_tcp_ports(
                 session_handler,
@@ -61,6 +63,7 @@ pub mod FlowsLayer {
 
             (*match_spec).tcp_valid = 0;
             (*match_spec).udp_valid = 1;
+            (*match_spec).icmp_valid = 0;
 
             p4_pd_l2_switching_extract_flows_ports_table_add_with_get_flows_udp_ports(
                 session_handler,
@@ -68,6 +71,18 @@ pub mod FlowsLayer {
                 match_spec,
                 entry_hdl,
             );
+
+            // ICMP type and code take the place of source and destination port
+            (*match_spec).tcp_valid = 0;
+            (*match_spec).udp_valid = 0;
+            (*match_spec).icmp_valid = 1;
+
+            p4_pd_l2_switching_extract_flows_ports_table_add_with_get_flows_icmp_type_code(
+                session_handler,
+                resolve_dev_target(),
+                match_spec,
+                entry_hdl,
+            );
         }
     }
 
@@ -84,57 +99,17 @@ pub mod FlowsLayer {
         }
     }
 
-    pub fn set_flows(session_handler: u32, dev_port_ingress: u16) {
-        unsafe {
-            let match_spec: *mut p4_pd_l2_switching_feature_match_spec_t =
-                malloc(mem::size_of::<p4_pd_l2_switching_feature_match_spec_t>()) as *mut p4_pd_l2_switching_feature_match_spec_t;
-            let action_spec: *mut p4_pd_l2_switching_feature_enable_action_spec_t =
-                malloc(mem::size_of::<p4_pd_l2_switching_feature_enable_action_spec_t>())
-                    as *mut p4_pd_l2_switching_feature_enable_action_spec_t;
-            let entry_hdl: *mut p4_pd_entry_hdl_t = malloc(mem::size_of::<p4_pd_entry_hdl_t>()) as *mut p4_pd_entry_hdl_t;
-
-            (*match_spec).ig_intr_md_ingress_port = dev_port_ingress;
-            (*action_spec).action_hhd = 0;
-            (*action_spec).action_flows = 1;
-
-            p4_pd_l2_switching_feature_table_add_with_feature_enable(
-                session_handler,
-                resolve_dev_target(),
-                match_spec,
-                action_spec,
-                entry_hdl,
-            );
-            println!("Enabled Flows {}", *entry_hdl);
-        }
-    }
-
-    pub fn reset_flows_table(session_handler: u32) {
-        unsafe {
-            let mut status: p4_pd_status_t = 0;
-            while status == 0 {
-                let index: *mut i32 = malloc(mem::size_of::<i32>()) as *mut i32;
-                status = p4_pd_l2_switching_feature_get_first_entry_handle(session_handler, resolve_dev_target(), index);
-
-                // todo: we will have to verify that it is the HHD entry
-                // assuming that there will be more features
-                if status == 0 {
-                    p4_pd_l2_switching_feature_table_delete(session_handler, 0 as u8, *index as p4_pd_entry_hdl_t);
-                    println!("Delete Flows Setting, Handle {}", *index);
-                }
-            }
-        }
-    }
-
     unsafe extern "C" fn callback(
         sess_hdl: p4_pd_sess_hdl_t,
         msg: *mut p4_pd_l2_switching_ipv4_flows_tuple_plus_hash_digest_msg_t,
         _callback_fn_cookie: *mut c_void,
     ) -> u32 {
         let mut entry = (*msg).entries;
+        let now = current_time_in_millis();
 
         for _ in 0..(*msg).num_entries {
             println!(
-                "learned: {:?} {} {:?} {} {} {} {}",
+                "learned: {:?} {} {:?} {} {} {} {} {}",
                 Ipv4Addr::from((*entry).ipv4_srcAddr),
                 (*entry).md_flows_metadata_srcPort,
                 Ipv4Addr::from((*entry).ipv4_dstAddr),
@@ -142,10 +117,11 @@ pub mod FlowsLayer {
                 (*entry).ipv4_protocol,
                 (*entry).md_flows_metadata_hash1,
                 (*entry).md_flows_metadata_hash2,
+                (*entry).ig_intr_md_ingress_port,
             );
 
-            // don't learn the ominous 0.0.0.0
-            if (*entry).md_flows_metadata_srcPort != 0 {
+            // only digests of IPv4 packets carry a valid flow, ports are 0 for protocols without ports
+            if (*entry).md_flows_metadata_valid == 1 {
                 FlowsManager::add_learned_flow(Flow {
                     src_addr: Ipv4Addr::from((*entry).ipv4_srcAddr).to_string(),
                     src_addr_int: (*entry).ipv4_srcAddr,
@@ -156,6 +132,20 @@ pub mod FlowsLayer {
                     ipv4_protocol: (*entry).ipv4_protocol,
                     hash1: (*entry).md_flows_metadata_hash1,
                     hash2: (*entry).md_flows_metadata_hash2,
+                    sketch_hashes: vec![
+                        (*entry).md_flows_metadata_hash1,
+                        (*entry).md_flows_metadata_hash2,
+                        (*entry).md_flows_metadata_hash3,
+                        (*entry).md_flows_metadata_hash4,
+                    ],
+                    ingress_port: HWManager::convert_dev_port_to_chassis_port(&((*entry).ig_intr_md_ingress_port as u32)),
+                    session: String::new(),
+                    first_seen: now,
+                    last_seen: now,
+                    packets: 0,
+                    bytes: 0,
+                    collision: FlowCollision::None,
+                    confidence: 1.0,
                 });
             };
 
@@ -179,6 +169,13 @@ pub mod FlowsLayer {
         }
     }
 
+    pub fn reset_all_bloomfilters(session_handler: u32) {
+        unsafe {
+            p4_pd_l2_switching_register_reset_all_flows_bloom_filter_1(session_handler, resolve_dev_target());
+            p4_pd_l2_switching_register_reset_all_flows_bloom_filter_2(session_handler, resolve_dev_target());
+        }
+    }
+
     fn resolve_dev_target() -> p4_pd_dev_target_t {
         p4_pd_dev_target_t {
             device_id: 0 as i32,
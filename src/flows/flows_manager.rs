@@ -20,13 +20,42 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
 // THE SOFTWARE.
 
-use flows::FlowsLayer;
-use hw::HWManager;
+use event::{EventManager, EventType};
+use feature::{Feature, FeatureManager};
+use flows::{FlowStore, FlowsCounter, FlowsEvictionPolicy, FlowsLayer};
+use rustc_serialize::{Encodable, Encoder};
+use std::collections::{HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::mem;
 use std::sync::Mutex;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-#[derive(Clone, Debug, Eq, PartialEq, RustcEncodable)]
+// how many sketch rows of a flow share their counter with other learned flows
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FlowCollision {
+    None,
+    Partial,
+    Full,
+}
+
+impl FlowCollision {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            FlowCollision::None => "none",
+            FlowCollision::Partial => "partial",
+            FlowCollision::Full => "full",
+        }
+    }
+}
+
+impl Encodable for FlowCollision {
+    fn encode<S: Encoder>(&self, encoder: &mut S) -> Result<(), S::Error> {
+        encoder.emit_str(self.name())
+    }
+}
+
+#[derive(Clone, Debug, RustcEncodable)]
 pub struct Flow {
     pub src_addr: String,
     pub src_addr_int: u32,
@@ -37,118 +66,553 @@ pub struct Flow {
     pub ipv4_protocol: u8,
     pub hash1: u16,
     pub hash2: u16,
+    // HHD counter index in each count-min sketch row
+    pub sketch_hashes: Vec<u16>,
+    pub ingress_port: u32,
+    pub session: String,
+    pub first_seen: u64,
+    pub last_seen: u64,
+    pub packets: u64,
+    pub bytes: u64,
+    pub collision: FlowCollision,
+    pub confidence: f64,
+}
+
+// two records describe the same flow if tuple and hashes match,
+// timestamps and counters are not part of the identity
+impl PartialEq for Flow {
+    fn eq(&self, other: &Flow) -> bool {
+        self.src_addr_int == other.src_addr_int && self.src_port == other.src_port && self.dst_addr_int == other.dst_addr_int
+            && self.dst_port == other.dst_port && self.ipv4_protocol == other.ipv4_protocol && self.hash1 == other.hash1
+            && self.hash2 == other.hash2
+    }
+}
+
+impl Eq for Flow {}
+
+impl Hash for Flow {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.src_addr_int.hash(state);
+        self.src_port.hash(state);
+        self.dst_addr_int.hash(state);
+        self.dst_port.hash(state);
+        self.ipv4_protocol.hash(state);
+        self.hash1.hash(state);
+        self.hash2.hash(state);
+    }
+}
+
+pub const DEFAULT_SESSION: &'static str = "default";
+
+// flows sessions of HHD are named after this prefix, only HHD starts and removes them
+pub const HHD_SESSION_PREFIX: &'static str = "hhd";
+
+#[derive(Clone, Debug, RustcEncodable)]
+pub struct FlowsSessionStatus {
+    pub name: String,
+    pub ports_ingress: Vec<u32>,
+    pub running: bool,
+    pub continuous: bool,
+    pub max_number_of_flows: u32,
+    pub number_of_learned_flows: usize,
+    pub eviction_policy: String,
+    pub number_of_dropped_flows: u64,
+    pub number_of_evicted_flows: u64,
+}
+
+struct FlowsSession {
+    ports_ingress: Vec<u32>,
+    running: bool,
+    continuous: bool,
+    generation: u64,
+    learned_flows: FlowStore,
 }
 
 pub struct FlowsManager {
     session_handler: u32,
-    flowmanager_running: bool,
-    max_number_of_flows: u16,
-    current_number_of_flows: u16,
-    learned_flows: Vec<Flow>,
+    sessions: HashMap<String, FlowsSession>,
+    port_sessions: HashMap<u32, String>,
+    next_generation: u64,
+    idle_timeout_in_seconds: u32,
+    epoch_in_seconds: u32,
+    eviction_policy: FlowsEvictionPolicy,
+    eviction_grace_period_in_seconds: u32,
+    last_epoch: u64,
+    history_size: usize,
+    flow_history: VecDeque<Flow>,
+    keep_removed_flows: bool,
+    removed_flows: Vec<Flow>,
 }
 
 lazy_static! {
     static ref MANAGER: Mutex<FlowsManager> = Mutex::new(
         FlowsManager{
             session_handler: 0,
-            flowmanager_running: false,
-            max_number_of_flows: 100,
-            current_number_of_flows: 0,
-            learned_flows: Vec::new(),
+            sessions: HashMap::new(),
+            port_sessions: HashMap::new(),
+            next_generation: 0,
+            idle_timeout_in_seconds: 60,
+            epoch_in_seconds: 10,
+            eviction_policy: FlowsEvictionPolicy::DropNew,
+            eviction_grace_period_in_seconds: 10,
+            last_epoch: 0,
+            history_size: 10000,
+            flow_history: VecDeque::new(),
+            keep_removed_flows: false,
+            removed_flows: Vec::new(),
         });
 }
 
 impl FlowsManager {
-    pub fn init() {
+    pub fn init(
+        idle_timeout_in_seconds: u32,
+        epoch_in_seconds: u32,
+        history_size: usize,
+        eviction_policy: FlowsEvictionPolicy,
+        eviction_grace_period_in_seconds: u32,
+    ) {
         let mut manager = MANAGER.lock().unwrap();
         manager.session_handler = FlowsLayer::init();
+        manager.idle_timeout_in_seconds = idle_timeout_in_seconds;
+        manager.epoch_in_seconds = epoch_in_seconds;
+        manager.eviction_policy = eviction_policy;
+        manager.eviction_grace_period_in_seconds = eviction_grace_period_in_seconds;
+        manager.history_size = history_size;
         FlowsLayer::setup_tables(manager.session_handler);
         FlowsLayer::register_callback_function(manager.session_handler);
+
+        println!(
+            "Flows Idle Timeout {}s, Epoch {}s, History Size {}, Eviction Policy {}, Eviction Grace Period {}s",
+            idle_timeout_in_seconds,
+            epoch_in_seconds,
+            history_size,
+            eviction_policy.name(),
+            eviction_grace_period_in_seconds
+        );
+        let _ = thread::Builder::new().name("flows-aging".to_string()).spawn(move || loop {
+            thread::sleep(Duration::from_secs(1));
+            age_flows();
+            start_new_epoch_if_due();
+        });
     }
 
-    pub fn set_flow_learning_for_time_window(chassis_port_ingress: u32, max_number_of_flows: u32, time_window_in_seconds: u32) {
-        let mut manager = MANAGER.lock().unwrap();
-        if manager.flowmanager_running {
-            return;
-        };
+    pub fn set_flow_learning_for_time_window(
+        session_name: &str,
+        chassis_ports_ingress: &Vec<u32>,
+        max_number_of_flows: u32,
+        eviction_policy: Option<FlowsEvictionPolicy>,
+        time_window_in_seconds: u32,
+    ) -> Result<(), String> {
+        let generation = {
+            let mut manager = MANAGER.lock().unwrap();
+            if manager.is_session_running(session_name) {
+                return Err(format!("Flows session {} is already running", session_name));
+            }
+            manager.verify_ports_available(session_name, chassis_ports_ingress)?;
 
-        manager.flowmanager_running = true;
-        manager.learned_flows = Vec::with_capacity(max_number_of_flows as usize);
-        manager.max_number_of_flows = max_number_of_flows as u16;
+            manager.start_session(session_name, max_number_of_flows, eviction_policy, false);
+            for chassis_port_ingress in chassis_ports_ingress {
+                manager.add_port_to_session(session_name, *chassis_port_ingress);
+            }
+            manager.sessions[session_name].generation
+        };
 
+        let session_name = session_name.to_string();
         let _ = thread::Builder::new().name("flows_learner".to_string()).spawn(move || {
             thread::sleep(Duration::from_secs(time_window_in_seconds as u64));
-            FlowsManager::reset_flows_table();
-            FlowsManager::reset_learned_flows();
-            MANAGER.lock().unwrap().flowmanager_running = false;
-            println!("Flows Learning turned off");
+            let mut manager = MANAGER.lock().unwrap();
+            let expired = match manager.sessions.get(&session_name) {
+                Some(session) => session.running && session.generation == generation,
+                None => false,
+            };
+            if expired {
+                manager.stop_session(&session_name);
+            }
         });
 
-        let dev_port_ingress = HWManager::convert_chassis_port_to_dev_port(&chassis_port_ingress);
-        FlowsLayer::set_flows(manager.session_handler, dev_port_ingress as u16);
+        Ok(())
+    }
 
-        println!("Flows Learning turned on on {}", chassis_port_ingress);
+    // learns until stopped, bloom filters get cleared every epoch so that active flows show up again
+    pub fn set_continuous_flow_learning(
+        session_name: &str,
+        chassis_ports_ingress: &Vec<u32>,
+        max_number_of_flows: u32,
+        eviction_policy: Option<FlowsEvictionPolicy>,
+    ) -> Result<(), String> {
+        let mut manager = MANAGER.lock().unwrap();
+        if manager.is_session_running(session_name) {
+            return Err(format!("Flows session {} is already running", session_name));
+        }
+        manager.verify_ports_available(session_name, chassis_ports_ingress)?;
+
+        manager.start_session(session_name, max_number_of_flows, eviction_policy, true);
+        for chassis_port_ingress in chassis_ports_ingress {
+            manager.add_port_to_session(session_name, *chassis_port_ingress);
+        }
+
+        Ok(())
     }
 
-    pub fn start_flow_learning(chassis_port_ingress: u32, max_number_of_flows: u16) {
+    // either all ports get added to the session or none
+    pub fn start_flow_learning(session_name: &str, chassis_ports_ingress: &Vec<u32>, max_number_of_flows: u32) -> Result<(), String> {
         let mut manager = MANAGER.lock().unwrap();
-        if !manager.flowmanager_running {
-            manager.flowmanager_running = true;
-            manager.learned_flows = Vec::with_capacity(max_number_of_flows as usize);
-            manager.max_number_of_flows = max_number_of_flows;
+        manager.verify_ports_available(session_name, chassis_ports_ingress)?;
+
+        if !manager.is_session_running(session_name) {
+            manager.start_session(session_name, max_number_of_flows, None, false);
         };
+        for chassis_port_ingress in chassis_ports_ingress {
+            manager.add_port_to_session(session_name, *chassis_port_ingress);
+        }
 
-        let dev_port_ingress = HWManager::convert_chassis_port_to_dev_port(&chassis_port_ingress);
-        FlowsLayer::set_flows(manager.session_handler, dev_port_ingress as u16);
+        Ok(())
+    }
 
-        println!("Flows Learning turned on on {}", chassis_port_ingress);
+    pub fn stop_flow_learning(session_name: &str) {
+        MANAGER.lock().unwrap().stop_session(session_name);
     }
 
-    pub fn stop_flow_learning() {
-        FlowsManager::reset_flows_table();
-        FlowsManager::reset_learned_flows();
-        MANAGER.lock().unwrap().flowmanager_running = false;
-        println!("Flows Learning turned off");
+    pub fn remove_session(session_name: &str) -> bool {
+        let mut manager = MANAGER.lock().unwrap();
+        if !manager.sessions.contains_key(session_name) {
+            return false;
+        }
+
+        manager.stop_session(session_name);
+        let mut session = manager.sessions.remove(session_name).unwrap();
+        for flow in session.learned_flows.drain() {
+            manager.add_to_history(flow);
+        }
+        println!("Flows session {} removed", session_name);
+        true
+    }
+
+    pub fn get_sessions() -> Vec<FlowsSessionStatus> {
+        let manager = MANAGER.lock().unwrap();
+        let mut result: Vec<FlowsSessionStatus> = manager
+            .sessions
+            .iter()
+            .map(|(name, session)| FlowsSessionStatus {
+                name: name.clone(),
+                ports_ingress: session.ports_ingress.clone(),
+                running: session.running,
+                continuous: session.continuous,
+                max_number_of_flows: session.learned_flows.capacity() as u32,
+                number_of_learned_flows: session.learned_flows.len(),
+                eviction_policy: session.learned_flows.eviction_policy().name().to_string(),
+                number_of_dropped_flows: session.learned_flows.number_of_dropped_flows(),
+                number_of_evicted_flows: session.learned_flows.number_of_evicted_flows(),
+            })
+            .collect();
+        result.sort_by(|a, b| a.name.cmp(&b.name));
+        result
     }
 
     pub fn get_learned_flows() -> Vec<Flow> {
-        MANAGER.lock().unwrap().learned_flows.clone()
+        let manager = MANAGER.lock().unwrap();
+        let mut result = Vec::new();
+        for session in manager.sessions.values() {
+            result.extend(session.learned_flows.to_vec());
+        }
+        manager.annotate_collisions(&mut result);
+        result
     }
 
-    pub fn get_learned_flows_and_reset() -> Vec<Flow> {
+    // flow export picks up the final counters of aged, evicted and dropped flows with the next learned flows
+    pub fn keep_removed_flows() {
+        MANAGER.lock().unwrap().keep_removed_flows = true;
+    }
+
+    // the flows removed since the last call, taken together with the learned flows so no flow is in both or neither
+    pub fn get_learned_and_removed_flows() -> (Vec<Flow>, Vec<Flow>) {
         let mut manager = MANAGER.lock().unwrap();
-        let learned_flows = manager.learned_flows.clone();
-        manager.learned_flows = Vec::with_capacity(manager.max_number_of_flows as usize);
-        manager.current_number_of_flows = 0;
-        for flow_to_delete in learned_flows.clone() {
-            FlowsLayer::reset_bloomfilters(manager.session_handler, &vec![flow_to_delete.hash1, flow_to_delete.hash2]);
+        let mut learned_flows = Vec::new();
+        for session in manager.sessions.values() {
+            learned_flows.extend(session.learned_flows.to_vec());
+        }
+        let removed_flows = mem::replace(&mut manager.removed_flows, Vec::new());
+        (learned_flows, removed_flows)
+    }
+
+    pub fn get_learned_flows_for_session(session_name: &str) -> Option<Vec<Flow>> {
+        let manager = MANAGER.lock().unwrap();
+        match manager.sessions.get(session_name) {
+            Some(session) => {
+                let mut result = session.learned_flows.to_vec();
+                manager.annotate_collisions(&mut result);
+                Some(result)
+            }
+            None => None,
         }
-        learned_flows
     }
 
-    pub fn get_current_number_of_flows() -> u16 {
-        MANAGER.lock().unwrap().current_number_of_flows
+    pub fn get_flow_history() -> Vec<Flow> {
+        MANAGER.lock().unwrap().flow_history.iter().cloned().collect()
+    }
+
+    pub fn get_flows_active_between(from: u64, to: u64) -> Vec<Flow> {
+        let manager = MANAGER.lock().unwrap();
+        let mut result: Vec<Flow> = manager
+            .flow_history
+            .iter()
+            .filter(|flow| flow.first_seen <= to && flow.last_seen >= from)
+            .cloned()
+            .collect();
+        for session in manager.sessions.values() {
+            result.extend(session.learned_flows.to_vec().into_iter().filter(|flow| flow.first_seen <= to && flow.last_seen >= from));
+        }
+        result
     }
 
-    pub fn reset_learned_flows() {
+    pub fn update_flow_counters(flow_counters: &Vec<(Flow, u64, u64)>) {
+        let now = current_time_in_millis();
         let mut manager = MANAGER.lock().unwrap();
-        for flow_to_delete in manager.learned_flows.clone() {
-            FlowsLayer::reset_bloomfilters(manager.session_handler, &vec![flow_to_delete.hash1, flow_to_delete.hash2]);
+        for &(ref flow, packets, bytes) in flow_counters {
+            if packets == 0 {
+                continue;
+            }
+
+            match manager.sessions.get_mut(&flow.session) {
+                Some(session) => session.learned_flows.add_counters(flow, packets, bytes, now),
+                None => {}
+            }
+        }
+    }
+
+    pub fn get_current_number_of_flows(session_name: &str) -> u32 {
+        match MANAGER.lock().unwrap().sessions.get(session_name) {
+            Some(session) => session.learned_flows.len() as u32,
+            None => 0,
+        }
+    }
+
+    // evict-smallest ranks the flows of the session by this counter
+    pub fn set_eviction_counter(session_name: &str, eviction_counter: FlowsCounter) {
+        if let Some(session) = MANAGER.lock().unwrap().sessions.get_mut(session_name) {
+            session.learned_flows.set_eviction_counter(eviction_counter);
         }
-        manager.current_number_of_flows = 0;
     }
 
-    pub fn add_learned_flow(flow: Flow) {
+    // new flows the session didn't learn because its flow table was full
+    pub fn get_number_of_dropped_flows(session_name: &str) -> u64 {
+        match MANAGER.lock().unwrap().sessions.get(session_name) {
+            Some(session) => session.learned_flows.number_of_dropped_flows(),
+            None => 0,
+        }
+    }
+
+    pub fn add_learned_flow(mut flow: Flow) {
         let mut manager = MANAGER.lock().unwrap();
-        manager.current_number_of_flows = manager.current_number_of_flows + 1;
-        if manager.current_number_of_flows <= manager.max_number_of_flows {
-            manager.learned_flows.push(flow.clone());
-        } else {
-            println!("Dropped learned flow, number is limited to {}", manager.max_number_of_flows);
+        let session_name = match manager.port_sessions.get(&flow.ingress_port) {
+            Some(session_name) => session_name.clone(),
+            None => {
+                println!("Dropped learned flow, no session for port {}", flow.ingress_port);
+                return;
+            }
+        };
+
+        let evicted_flow = {
+            let session = manager.sessions.get_mut(&session_name).unwrap();
+
+            // flows show up again after their bloom filter bits got cleared
+            if session.learned_flows.touch(&flow, flow.last_seen) {
+                return;
+            }
+
+            flow.session = session_name.clone();
+            let learned_flow = flow.clone();
+            match session.learned_flows.insert(flow) {
+                Ok(evicted_flow) => {
+                    EventManager::publish(EventType::FlowLearned, &learned_flow);
+                    evicted_flow
+                }
+                Err(_) => {
+                    println!(
+                        "Dropped learned flow, number is limited to {} for session {}",
+                        session.learned_flows.capacity(),
+                        session_name
+                    );
+                    None
+                }
+            }
+        };
+
+        // the bloom filter bits of the evicted flow stay set, it only gets learned again in the next epoch
+        match evicted_flow {
+            Some(evicted_flow) => manager.add_to_history(evicted_flow),
+            None => {}
+        }
+    }
+}
+
+impl FlowsManager {
+    // flows sharing a hash with other learned flows share their HHD counter
+    fn annotate_collisions(&self, flows: &mut Vec<Flow>) {
+        for flow in flows.iter_mut() {
+            let mut row_flows = vec![0; flow.sketch_hashes.len()];
+            for session in self.sessions.values() {
+                for (row, hash) in flow.sketch_hashes.iter().enumerate() {
+                    row_flows[row] = row_flows[row] + session.learned_flows.number_of_flows_with_sketch_hash(row, *hash);
+                }
+            }
+            let shared_rows = row_flows.iter().filter(|flows| **flows > 1).count();
+            let least_shared = row_flows.iter().map(|flows| (*flows).max(1)).min().unwrap_or(1);
+
+            // the smallest count is used as estimate, it's only off if the counters of all rows are shared
+            flow.collision = match shared_rows {
+                0 => FlowCollision::None,
+                rows if rows == row_flows.len() => FlowCollision::Full,
+                _ => FlowCollision::Partial,
+            };
+            flow.confidence = 1.0 / least_shared as f64;
         }
     }
 
-    pub fn reset_flows_table() {
-        FlowsLayer::reset_flows_table(MANAGER.lock().unwrap().session_handler);
+    fn is_session_running(&self, session_name: &str) -> bool {
+        match self.sessions.get(session_name) {
+            Some(session) => session.running,
+            None => false,
+        }
+    }
+
+    fn verify_ports_available(&self, session_name: &str, chassis_ports_ingress: &Vec<u32>) -> Result<(), String> {
+        for chassis_port_ingress in chassis_ports_ingress {
+            match self.port_sessions.get(chassis_port_ingress) {
+                Some(owner) if owner != session_name => {
+                    return Err(format!("Port {} is already used by flows session {}", chassis_port_ingress, owner));
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn start_session(&mut self, session_name: &str, max_number_of_flows: u32, eviction_policy: Option<FlowsEvictionPolicy>, continuous: bool) {
+        // flows of a previous run of the same session move to the history
+        match self.sessions.remove(session_name) {
+            Some(mut previous_session) => for flow in previous_session.learned_flows.drain() {
+                self.add_to_history(flow);
+            },
+            None => {}
+        }
+
+        self.next_generation = self.next_generation + 1;
+        self.sessions.insert(
+            session_name.to_string(),
+            FlowsSession {
+                ports_ingress: Vec::new(),
+                running: true,
+                continuous: continuous,
+                generation: self.next_generation,
+                learned_flows: FlowStore::new(
+                    max_number_of_flows as usize,
+                    eviction_policy.unwrap_or(self.eviction_policy),
+                    self.eviction_grace_period_in_seconds as u64 * 1000,
+                ),
+            },
+        );
+    }
+
+    fn add_port_to_session(&mut self, session_name: &str, chassis_port_ingress: u32) {
+        let session = self.sessions.get_mut(session_name).unwrap();
+        if session.ports_ingress.contains(&chassis_port_ingress) {
+            return;
+        }
+
+        session.ports_ingress.push(chassis_port_ingress);
+        self.port_sessions.insert(chassis_port_ingress, session_name.to_string());
+        FeatureManager::enable(Feature::Flows, chassis_port_ingress);
+
+        println!("Flows Learning turned on on {} for session {}", chassis_port_ingress, session_name);
+    }
+
+    // stops learning but keeps the learned flows readable until the session gets restarted or removed
+    fn stop_session(&mut self, session_name: &str) {
+        let session_handler = self.session_handler;
+        let ports_ingress = match self.sessions.get_mut(session_name) {
+            Some(session) => {
+                if !session.running {
+                    return;
+                }
+
+                for flow_to_delete in session.learned_flows.to_vec() {
+                    FlowsLayer::reset_bloomfilters(session_handler, &vec![flow_to_delete.hash1, flow_to_delete.hash2]);
+                }
+                session.running = false;
+                session.ports_ingress.clone()
+            }
+            None => return,
+        };
+
+        for chassis_port_ingress in ports_ingress {
+            self.port_sessions.remove(&chassis_port_ingress);
+            FeatureManager::disable(Feature::Flows, chassis_port_ingress);
+        }
+
+        println!("Flows Learning turned off for session {}", session_name);
+    }
+
+    fn add_to_history(&mut self, flow: Flow) {
+        if self.keep_removed_flows {
+            self.removed_flows.push(flow.clone());
+        }
+        if self.history_size == 0 {
+            return;
+        }
+
+        while self.flow_history.len() >= self.history_size {
+            self.flow_history.pop_front();
+        }
+        self.flow_history.push_back(flow);
+    }
+}
+
+pub fn current_time_in_millis() -> u64 {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+    now.as_secs() * 1000 + (now.subsec_nanos() / 1000000) as u64
+}
+
+fn age_flows() {
+    let now = current_time_in_millis();
+    let mut manager = MANAGER.lock().unwrap();
+    let session_handler = manager.session_handler;
+    let idle_timeout = manager.idle_timeout_in_seconds as u64 * 1000;
+
+    let mut idle_flows = Vec::new();
+    for session in manager.sessions.values_mut().filter(|session| session.running) {
+        idle_flows.extend(session.learned_flows.remove_idle(now, idle_timeout));
+    }
+
+    for flow in idle_flows {
+        // let the data plane learn the flow again in case it comes back
+        FlowsLayer::reset_bloomfilters(session_handler, &vec![flow.hash1, flow.hash2]);
+        println!("Aged flow: {:?} {} {:?} {}", flow.src_addr, flow.src_port, flow.dst_addr, flow.dst_port);
+        EventManager::publish(EventType::FlowAged, &flow);
+        manager.add_to_history(flow);
+    }
+}
+
+fn start_new_epoch_if_due() {
+    let now = current_time_in_millis();
+    let mut manager = MANAGER.lock().unwrap();
+    if !manager.sessions.values().any(|session| session.running && session.continuous) {
+        return;
+    }
+
+    if now.saturating_sub(manager.last_epoch) < manager.epoch_in_seconds as u64 * 1000 {
+        return;
+    }
+
+    // the bloom filters are shared, other running sessions only keep their bits if just the continuous flows get reset
+    if manager.sessions.values().all(|session| !session.running || session.continuous) {
+        FlowsLayer::reset_all_bloomfilters(manager.session_handler);
+    } else {
+        for session in manager.sessions.values().filter(|session| session.running && session.continuous) {
+            for flow in session.learned_flows.to_vec() {
+                FlowsLayer::reset_bloomfilters(manager.session_handler, &vec![flow.hash1, flow.hash2]);
+            }
+        }
     }
+    manager.last_epoch = now;
 }
@@ -0,0 +1,220 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2018 AT&T. All other rights reserved.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+use flows::Flow;
+use std::net::Ipv4Addr;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FlowsSortOrder {
+    None,
+    PacketsAscending,
+    PacketsDescending,
+    BytesAscending,
+    BytesDescending,
+}
+
+#[derive(Clone, Debug)]
+pub struct FlowsQuery {
+    pub src_prefix: Option<(u32, u32)>,
+    pub dst_prefix: Option<(u32, u32)>,
+    pub ipv4_protocol: Option<u8>,
+    pub src_port: Option<u16>,
+    pub dst_port: Option<u16>,
+    pub ingress_port: Option<u32>,
+    pub min_packets: Option<u64>,
+    pub min_bytes: Option<u64>,
+    pub sort_order: FlowsSortOrder,
+    pub offset: usize,
+    pub limit: Option<usize>,
+}
+
+impl FlowsQuery {
+    pub fn new() -> FlowsQuery {
+        FlowsQuery {
+            src_prefix: None,
+            dst_prefix: None,
+            ipv4_protocol: None,
+            src_port: None,
+            dst_port: None,
+            ingress_port: None,
+            min_packets: None,
+            min_bytes: None,
+            sort_order: FlowsSortOrder::None,
+            offset: 0,
+            limit: None,
+        }
+    }
+
+    // parses url query parameters like src=10.0.0.0/8&protocol=6&sort=packets&order=desc&limit=20
+    pub fn from_query_string(query: &str) -> Result<FlowsQuery, String> {
+        let mut result = FlowsQuery::new();
+        let mut sort_by: Option<&'static str> = None;
+        let mut ascending = false;
+
+        for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+            let mut parts = pair.splitn(2, '=');
+            let key = percent_decode(parts.next().unwrap())?;
+            let value = percent_decode(parts.next().unwrap_or(""))?;
+            let key = key.as_str();
+
+            match key {
+                "src" => result.src_prefix = Some(parse_prefix(&value)?),
+                "dst" => result.dst_prefix = Some(parse_prefix(&value)?),
+                "protocol" => result.ipv4_protocol = Some(parse_number(key, &value)?),
+                "src_port" => result.src_port = Some(parse_number(key, &value)?),
+                "dst_port" => result.dst_port = Some(parse_number(key, &value)?),
+                "ingress_port" => result.ingress_port = Some(parse_number(key, &value)?),
+                "min_packets" => result.min_packets = Some(parse_number(key, &value)?),
+                "min_bytes" => result.min_bytes = Some(parse_number(key, &value)?),
+                "offset" => result.offset = parse_number(key, &value)?,
+                "limit" => result.limit = Some(parse_number(key, &value)?),
+                "sort" => match value.as_str() {
+                    "packets" => sort_by = Some("packets"),
+                    "bytes" => sort_by = Some("bytes"),
+                    _ => return Err(format!("can't sort by {}", value)),
+                },
+                "order" => match value.as_str() {
+                    "asc" => ascending = true,
+                    "desc" => ascending = false,
+                    _ => return Err(format!("unknown order {}", value)),
+                },
+                _ => return Err(format!("unknown parameter {}", key)),
+            }
+        }
+
+        result.sort_order = match (sort_by, ascending) {
+            (Some("packets"), true) => FlowsSortOrder::PacketsAscending,
+            (Some("packets"), false) => FlowsSortOrder::PacketsDescending,
+            (Some("bytes"), true) => FlowsSortOrder::BytesAscending,
+            (Some("bytes"), false) => FlowsSortOrder::BytesDescending,
+            _ => FlowsSortOrder::None,
+        };
+
+        Ok(result)
+    }
+
+    pub fn matches(&self, flow: &Flow) -> bool {
+        matches_prefix(&self.src_prefix, flow.src_addr_int) && matches_prefix(&self.dst_prefix, flow.dst_addr_int)
+            && matches_value(&self.ipv4_protocol, &flow.ipv4_protocol) && matches_value(&self.src_port, &flow.src_port)
+            && matches_value(&self.dst_port, &flow.dst_port) && matches_value(&self.ingress_port, &flow.ingress_port)
+            && self.min_packets.map_or(true, |min_packets| flow.packets >= min_packets)
+            && self.min_bytes.map_or(true, |min_bytes| flow.bytes >= min_bytes)
+    }
+
+    // returns the number of matching flows and the requested page of them
+    pub fn apply(&self, flows: Vec<Flow>) -> (usize, Vec<Flow>) {
+        let mut result: Vec<Flow> = flows.into_iter().filter(|flow| self.matches(flow)).collect();
+
+        match self.sort_order {
+            FlowsSortOrder::PacketsAscending => result.sort_by(|a, b| a.packets.cmp(&b.packets)),
+            FlowsSortOrder::PacketsDescending => result.sort_by(|a, b| b.packets.cmp(&a.packets)),
+            FlowsSortOrder::BytesAscending => result.sort_by(|a, b| a.bytes.cmp(&b.bytes)),
+            FlowsSortOrder::BytesDescending => result.sort_by(|a, b| b.bytes.cmp(&a.bytes)),
+            FlowsSortOrder::None => {}
+        }
+
+        let total = result.len();
+        let page = result
+            .into_iter()
+            .skip(self.offset)
+            .take(self.limit.unwrap_or(total))
+            .collect();
+
+        (total, page)
+    }
+}
+
+fn matches_prefix(prefix: &Option<(u32, u32)>, address: u32) -> bool {
+    match *prefix {
+        Some((network, mask)) => address & mask == network,
+        None => true,
+    }
+}
+
+fn matches_value<T: PartialEq>(expected: &Option<T>, value: &T) -> bool {
+    match *expected {
+        Some(ref expected) => expected == value,
+        None => true,
+    }
+}
+
+// decodes %XX escapes and + as space of a url query component
+pub fn percent_decode(value: &str) -> Result<String, String> {
+    let bytes = value.as_bytes();
+    let mut result: Vec<u8> = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        match bytes[index] {
+            b'%' => {
+                let escape = match bytes.get(index + 1..index + 3) {
+                    Some(hex) if hex.iter().all(|digit| (*digit as char).is_digit(16)) => {
+                        u8::from_str_radix(&String::from_utf8_lossy(hex), 16).ok()
+                    }
+                    _ => None,
+                };
+                match escape {
+                    Some(byte) => result.push(byte),
+                    None => return Err(format!("invalid escape in {}", value)),
+                }
+                index += 3;
+            }
+            b'+' => {
+                result.push(b' ');
+                index += 1;
+            }
+            byte => {
+                result.push(byte);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8(result).map_err(|_| format!("invalid escape in {}", value))
+}
+
+fn parse_number<T: FromStr>(key: &str, value: &str) -> Result<T, String> {
+    value.parse::<T>().map_err(|_| format!("invalid value {} for {}", value, key))
+}
+
+// "10.0.0.0/8" -> (network, mask), a plain address is treated as /32
+fn parse_prefix(value: &str) -> Result<(u32, u32), String> {
+    let mut parts = value.splitn(2, '/');
+    let address = match Ipv4Addr::from_str(parts.next().unwrap()) {
+        Ok(address) => u32::from(address),
+        Err(_) => return Err(format!("invalid address {}", value)),
+    };
+    let prefix_length = match parts.next() {
+        Some(prefix_length) => match prefix_length.parse::<u32>() {
+            Ok(prefix_length) if prefix_length <= 32 => prefix_length,
+            _ => return Err(format!("invalid prefix length {}", value)),
+        },
+        None => 32,
+    };
+
+    let mask = if prefix_length == 0 {
+        0
+    } else {
+        u32::max_value() << (32 - prefix_length)
+    };
+
+    Ok((address & mask, mask))
+}
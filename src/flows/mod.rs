@@ -20,8 +20,13 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
 // THE SOFTWARE.
 
+pub use self::flow_store::{FlowStore, FlowsCounter, FlowsEvictionPolicy};
 pub use self::flows_layer::FlowsLayer;
-pub use self::flows_manager::{Flow, FlowsManager};
+pub use self::flows_manager::{current_time_in_millis, Flow, FlowCollision, FlowsManager, FlowsSessionStatus, DEFAULT_SESSION,
+                              HHD_SESSION_PREFIX};
+pub use self::flows_query::{percent_decode, FlowsQuery, FlowsSortOrder};
 
+mod flow_store;
 mod flows_layer;
 mod flows_manager;
+mod flows_query;
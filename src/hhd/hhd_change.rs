@@ -0,0 +1,125 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2018 AT&T. All other rights reserved.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+use flows::Flow;
+use hhd::{HHDThreshold, HHDWindow};
+use std::collections::HashMap;
+
+// change of a flow's packet or byte count against the previous window
+#[derive(Clone, Debug, RustcEncodable)]
+pub struct HHDFlowChange {
+    pub flow: Flow,
+    pub previous_count: u64,
+    pub count: u64,
+    pub delta: i64,
+    // count over previous count, None for flows not counted in the previous window
+    pub ratio: Option<f64>,
+}
+
+// keeps the per-flow counts of the previous window and reports the flows whose count jumped,
+// a flow has to grow by at least the threshold and by at least the ratio, whichever are set,
+// flows not counted in the previous window need the threshold
+#[derive(Clone)]
+pub struct HHDChangeDetection {
+    pub threshold: Option<HHDThreshold>,
+    pub min_ratio: Option<f64>,
+    pub divert: bool,
+    limit: Option<u64>,
+    previous_counts: HashMap<Flow, u64>,
+}
+
+impl HHDChangeDetection {
+    pub fn new(threshold: Option<HHDThreshold>, min_ratio: Option<f64>, divert: bool) -> HHDChangeDetection {
+        HHDChangeDetection {
+            threshold: threshold,
+            min_ratio: min_ratio,
+            divert: divert,
+            limit: None,
+            previous_counts: HashMap::new(),
+        }
+    }
+
+    pub fn is_on(&self) -> bool {
+        self.threshold.is_some() || self.min_ratio.is_some()
+    }
+
+    pub fn reset(&mut self) {
+        self.limit = None;
+        self.previous_counts.clear();
+    }
+
+    // packet or byte increase a flow had to reach in the last window, if a threshold is set
+    pub fn limit(&self) -> Option<u64> {
+        self.limit
+    }
+
+    // the flows that changed enough, largest increase first
+    pub fn detect(&mut self, window: &HHDWindow) -> Vec<HHDFlowChange> {
+        self.limit = match self.threshold {
+            Some(threshold) => threshold.limit_for_window(window.metric, window.window_end.saturating_sub(window.window_start), window.port_rate),
+            None => None,
+        };
+
+        let mut changes: Vec<HHDFlowChange> = Vec::new();
+        if self.is_on() && (self.threshold.is_none() || self.limit.is_some()) {
+            for flow_count in window.flows.iter() {
+                let count = window.count(flow_count);
+                let previous_count = *self.previous_counts.get(&flow_count.flow).unwrap_or(&0);
+                if count <= previous_count {
+                    continue;
+                }
+
+                let change = HHDFlowChange {
+                    flow: flow_count.flow.clone(),
+                    previous_count: previous_count,
+                    count: count,
+                    delta: count as i64 - previous_count as i64,
+                    ratio: match previous_count {
+                        0 => None,
+                        previous_count => Some(count as f64 / previous_count as f64),
+                    },
+                };
+                let delta_passes = match self.limit {
+                    Some(limit) => change.delta as u64 >= limit,
+                    None => true,
+                };
+                // flows new in this window have no ratio, only the threshold tells whether they are heavy
+                let ratio_passes = match (self.min_ratio, change.ratio) {
+                    (Some(min_ratio), Some(ratio)) => ratio >= min_ratio,
+                    (_, None) => self.limit.is_some(),
+                    (None, Some(_)) => true,
+                };
+                if delta_passes && ratio_passes {
+                    changes.push(change);
+                }
+            }
+            changes.sort_by(|a, b| b.delta.cmp(&a.delta));
+        }
+
+        self.previous_counts = window
+            .flows
+            .iter()
+            .map(|flow_count| (flow_count.flow.clone(), window.count(flow_count)))
+            .collect();
+        changes
+    }
+}
@@ -0,0 +1,127 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2018 AT&T. All other rights reserved.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+use hhd::{HHDWindow, HeavyHitter};
+
+// keeps the selected heavy hitters stable, a challenger replaces the incumbents only if it beats them
+// by the margin or stays selected for a number of consecutive windows, and never within the hold time,
+// incumbents no longer picked without a challenger get released once the hold time is over
+#[derive(Clone)]
+pub struct HHDHysteresis {
+    pub switch_margin_percent: f64,
+    pub switch_after_windows: u32,
+    pub min_hold_time_in_seconds: u32,
+    selected: Vec<HeavyHitter>,
+    selected_since: u64,
+    pending: Vec<HeavyHitter>,
+    pending_windows: u32,
+}
+
+impl HHDHysteresis {
+    pub fn new(switch_margin_percent: f64, switch_after_windows: u32, min_hold_time_in_seconds: u32) -> HHDHysteresis {
+        HHDHysteresis {
+            switch_margin_percent: switch_margin_percent,
+            switch_after_windows: switch_after_windows,
+            min_hold_time_in_seconds: min_hold_time_in_seconds,
+            selected: Vec::new(),
+            selected_since: 0,
+            pending: Vec::new(),
+            pending_windows: 0,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.selected.clear();
+        self.selected_since = 0;
+        self.pending.clear();
+        self.pending_windows = 0;
+    }
+
+    pub fn selected(&self) -> Vec<HeavyHitter> {
+        self.selected.clone()
+    }
+
+    // returns the heavy hitters that stay or become selected
+    pub fn select(&mut self, candidates: Vec<HeavyHitter>, window: &HHDWindow) -> Vec<HeavyHitter> {
+        if same_heavy_hitters(&candidates, &self.selected) {
+            // counts of the window, the set stays the same
+            self.selected = candidates;
+            self.pending.clear();
+            self.pending_windows = 0;
+            return self.selected.clone();
+        }
+
+        if same_heavy_hitters(&candidates, &self.pending) {
+            self.pending_windows = self.pending_windows + 1;
+        } else {
+            self.pending = candidates.clone();
+            self.pending_windows = 1;
+        }
+
+        let holding = !self.selected.is_empty() && window.window_end.saturating_sub(self.selected_since) < self.min_hold_time_in_seconds as u64 * 1000;
+        if holding {
+            return self.selected.clone();
+        }
+
+        // a shrinking set has nothing to replace the released heavy hitters with, no margin applies
+        if candidates.iter().all(|heavy_hitter| self.selected.contains(heavy_hitter)) {
+            self.selected = candidates;
+            self.pending.clear();
+            self.pending_windows = 0;
+            return self.selected.clone();
+        }
+
+        if !self.selected.is_empty() && !self.challengers_win(&candidates, window) {
+            return self.selected.clone();
+        }
+
+        self.selected = candidates;
+        self.selected_since = window.window_end;
+        self.pending.clear();
+        self.pending_windows = 0;
+        self.selected.clone()
+    }
+
+    fn challengers_win(&self, candidates: &Vec<HeavyHitter>, window: &HHDWindow) -> bool {
+        // with 1 every window would be consecutive, only the margin decides then
+        if self.switch_after_windows > 1 && self.pending_windows >= self.switch_after_windows {
+            return true;
+        }
+
+        let challengers: u64 = candidates
+            .iter()
+            .filter(|heavy_hitter| !self.selected.contains(heavy_hitter))
+            .map(|heavy_hitter| window.count_for(heavy_hitter))
+            .sum();
+        let incumbents: u64 = self.selected
+            .iter()
+            .filter(|heavy_hitter| !candidates.contains(heavy_hitter))
+            .map(|heavy_hitter| window.count_for(heavy_hitter))
+            .sum();
+
+        challengers as f64 >= incumbents as f64 * (1.0 + self.switch_margin_percent / 100.0)
+    }
+}
+
+fn same_heavy_hitters(a: &Vec<HeavyHitter>, b: &Vec<HeavyHitter>) -> bool {
+    a.len() == b.len() && a.iter().all(|heavy_hitter| b.contains(heavy_hitter))
+}
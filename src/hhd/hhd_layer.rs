@@ -29,6 +29,14 @@ pub mod HHDLayer {
     include!("../../gen-stub/bindings-taep.rs");
 
     use std::mem;
+    use std::os::raw::c_void;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    // longest to wait for the hardware sync of a counter array
+    const HW_SYNC_TIMEOUT_IN_MILLIS: u64 = 1000;
 
     pub fn init() -> u32 {
         unsafe {
@@ -39,127 +47,101 @@ pub mod HHDLayer {
         }
     }
 
-    pub fn set_hhd(session_handler: u32, dev_port_ingress: u16) {
-        unsafe {
-            let match_spec: *mut p4_pd_l2_switching_feature_match_spec_t =
-                malloc(mem::size_of::<p4_pd_l2_switching_feature_match_spec_t>()) as *mut p4_pd_l2_switching_feature_match_spec_t;
-            let action_spec: *mut p4_pd_l2_switching_feature_enable_action_spec_t =
-                malloc(mem::size_of::<p4_pd_l2_switching_feature_enable_action_spec_t>())
-                    as *mut p4_pd_l2_switching_feature_enable_action_spec_t;
-            let entry_hdl: *mut p4_pd_entry_hdl_t = malloc(mem::size_of::<p4_pd_entry_hdl_t>()) as *mut p4_pd_entry_hdl_t;
-
-            (*match_spec).ig_intr_md_ingress_port = dev_port_ingress;
-            (*action_spec).action_hhd = 1;
-            (*action_spec).action_flows = 0;
-
-            p4_pd_l2_switching_feature_table_add_with_feature_enable(
-                session_handler,
-                resolve_dev_target(),
-                match_spec,
-                action_spec,
-                entry_hdl,
-            );
-            println!("Enabled HHD {}", *entry_hdl);
-        }
-    }
+    // one hardware sync per sketch row, then all packet and byte counts get read from the synced shadow copy
+    pub fn sync_counters(session_handler: u32, rows: usize, width: u32) -> Vec<Vec<(u64, u64)>> {
+        let mut result: Vec<Vec<(u64, u64)>> = Vec::with_capacity(rows);
 
-    pub fn reset_hhd_table(session_handler: u32) {
         unsafe {
-            let mut status: p4_pd_status_t = 0;
-            while status == 0 {
-                let index: *mut i32 = malloc(mem::size_of::<i32>()) as *mut i32;
-                status = p4_pd_l2_switching_feature_get_first_entry_handle(session_handler, resolve_dev_target(), index);
-
-                // todo: we will have to verify that it is the HHD entry
-                // assuming that there will be more features
-                if status == 0 {
-                    p4_pd_l2_switching_feature_table_delete(session_handler, 0 as u8, *index as p4_pd_entry_hdl_t);
-                    println!("Delete HHD Setting, Handle {}", *index);
+            let counter_value: *mut p4_pd_counter_value_t = malloc(mem::size_of::<p4_pd_counter_value_t>()) as *mut p4_pd_counter_value_t;
+
+            for row in 0..rows {
+                hw_sync_counter(session_handler, row);
+
+                let mut counts: Vec<(u64, u64)> = Vec::with_capacity(width as usize);
+                for index in 0..width {
+                    read_counter_from_shadow(session_handler, row, index as u16, counter_value);
+                    counts.push(((*counter_value).packets, (*counter_value).bytes));
                 }
+                result.push(counts);
             }
         }
-    }
 
-    pub fn retrieve_current_packet_count(session_handler: u32, hashes: &Vec<u16>) -> u64 {
-        let mut result: u64;
+        result
+    }
 
+    // clears the sketch rows in one batch
+    pub fn reset_all_counters(session_handler: u32, rows: usize, width: u32) {
         unsafe {
-            let counter_value: *mut p4_pd_counter_value_t = malloc(mem::size_of::<p4_pd_counter_value_t>()) as *mut p4_pd_counter_value_t;
-
-            p4_pd_l2_switching_counter_read_count_hhd_hash_1(
-                session_handler,
-                resolve_dev_target(),
-                hashes[0] as i32,
-                COUNTER_READ_HW_SYNC as i32,
-                counter_value,
-            );
-            result = (*counter_value).packets;
-
-            p4_pd_l2_switching_counter_read_count_hhd_hash_2(
-                session_handler,
-                resolve_dev_target(),
-                hashes[1] as i32,
-                COUNTER_READ_HW_SYNC as i32,
-                counter_value,
-            );
-            result = result + (*counter_value).packets;
+            p4_pd_begin_batch(session_handler);
+            for row in 0..rows {
+                for index in 0..width {
+                    write_counter(
+                        session_handler,
+                        row,
+                        index as u16,
+                        p4_pd_counter_value {
+                            packets: 0 as u64,
+                            bytes: 0 as u64,
+                        },
+                    );
+                }
+            }
+            p4_pd_end_batch(session_handler, true);
         }
-
-        result
     }
 
-    pub fn retrieve_smallest_packet_count(session_handler: u32, hashes: &Vec<u16>) -> u64 {
-        let mut result: u64;
-
-        unsafe {
-            let counter_value: *mut p4_pd_counter_value_t = malloc(mem::size_of::<p4_pd_counter_value_t>()) as *mut p4_pd_counter_value_t;
+    // the callback may come after the timeout, so it owns a reference of its own to the done flag,
+    // the reference goes back if the sync doesn't get started
+    unsafe fn hw_sync_counter(session_handler: u32, row: usize) {
+        let done = Arc::new(AtomicBool::new(false));
+        let cookie = Arc::into_raw(done.clone()) as *mut c_void;
+        let dev_target = resolve_dev_target();
+        let status = match row {
+            0 => p4_pd_l2_switching_counter_hw_sync_count_hhd_hash_1(session_handler, dev_target, Some(hw_sync_done), cookie),
+            1 => p4_pd_l2_switching_counter_hw_sync_count_hhd_hash_2(session_handler, dev_target, Some(hw_sync_done), cookie),
+            2 => p4_pd_l2_switching_counter_hw_sync_count_hhd_hash_3(session_handler, dev_target, Some(hw_sync_done), cookie),
+            _ => p4_pd_l2_switching_counter_hw_sync_count_hhd_hash_4(session_handler, dev_target, Some(hw_sync_done), cookie),
+        };
+        if status != 0 {
+            drop(Arc::from_raw(cookie as *const AtomicBool));
+            println!("HHD counter sync of row {} failed with status {}, reading the previous shadow copy", row + 1, status);
+            return;
+        }
 
-            p4_pd_l2_switching_counter_read_count_hhd_hash_1(
-                session_handler,
-                resolve_dev_target(),
-                hashes[0] as i32,
-                COUNTER_READ_HW_SYNC as i32,
-                counter_value,
-            );
-            result = (*counter_value).packets;
-
-            p4_pd_l2_switching_counter_read_count_hhd_hash_2(
-                session_handler,
-                resolve_dev_target(),
-                hashes[1] as i32,
-                COUNTER_READ_HW_SYNC as i32,
-                counter_value,
-            );
-            if (*counter_value).packets < result {
-                result = (*counter_value).packets;
+        let started = Instant::now();
+        while !done.load(Ordering::SeqCst) {
+            if started.elapsed() > Duration::from_millis(HW_SYNC_TIMEOUT_IN_MILLIS) {
+                println!("HHD counter sync of row {} timed out, reading the previous shadow copy", row + 1);
+                return;
             }
+            thread::sleep(Duration::from_millis(1));
         }
+    }
 
-        result
+    unsafe extern "C" fn hw_sync_done(_device_id: i32, cookie: *mut c_void) {
+        let done = Arc::from_raw(cookie as *const AtomicBool);
+        done.store(true, Ordering::SeqCst);
     }
 
-    pub fn reset_counters(session_handler: u32, hashes: &Vec<u16>) {
-        unsafe {
-            p4_pd_l2_switching_counter_write_count_hhd_hash_1(
-                session_handler,
-                resolve_dev_target(),
-                hashes[0] as i32,
-                p4_pd_counter_value {
-                    packets: 0 as u64,
-                    bytes: 0 as u64,
-                },
-            );
-
-            p4_pd_l2_switching_counter_write_count_hhd_hash_2(
-                session_handler,
-                resolve_dev_target(),
-                hashes[1] as i32,
-                p4_pd_counter_value {
-                    packets: 0 as u64,
-                    bytes: 0 as u64,
-                },
-            );
-        }
+    unsafe fn read_counter_from_shadow(session_handler: u32, row: usize, hash: u16, counter_value: *mut p4_pd_counter_value_t) {
+        let dev_target = resolve_dev_target();
+        match row {
+            0 => p4_pd_l2_switching_counter_read_count_hhd_hash_1(session_handler, dev_target, hash as i32, 0, counter_value),
+            1 => p4_pd_l2_switching_counter_read_count_hhd_hash_2(session_handler, dev_target, hash as i32, 0, counter_value),
+            2 => p4_pd_l2_switching_counter_read_count_hhd_hash_3(session_handler, dev_target, hash as i32, 0, counter_value),
+            _ => p4_pd_l2_switching_counter_read_count_hhd_hash_4(session_handler, dev_target, hash as i32, 0, counter_value),
+        };
+    }
+
+    // every sketch row is a counter of its own in the P4 program
+    unsafe fn write_counter(session_handler: u32, row: usize, hash: u16, counter_value: p4_pd_counter_value_t) {
+        let dev_target = resolve_dev_target();
+        match row {
+            0 => p4_pd_l2_switching_counter_write_count_hhd_hash_1(session_handler, dev_target, hash as i32, counter_value),
+            1 => p4_pd_l2_switching_counter_write_count_hhd_hash_2(session_handler, dev_target, hash as i32, counter_value),
+            2 => p4_pd_l2_switching_counter_write_count_hhd_hash_3(session_handler, dev_target, hash as i32, counter_value),
+            _ => p4_pd_l2_switching_counter_write_count_hhd_hash_4(session_handler, dev_target, hash as i32, counter_value),
+        };
     }
 
     fn resolve_dev_target() -> p4_pd_dev_target_t {
@@ -20,157 +20,981 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
 // THE SOFTWARE.
 
-use flows::{Flow, FlowsManager};
-use hhd::HHDLayer;
-use hw::HWManager;
+use event::{EventManager, EventType};
+use feature::{Feature, FeatureManager};
+use flows::{current_time_in_millis, Flow, FlowCollision, FlowsCounter, FlowsManager, HHD_SESSION_PREFIX};
+use hhd::{create_policy, merge_heavy_hitters, HHDChangeDetection, HHDCounters, HHDFlowChange, HHDHysteresis, HHDLayer, HHDPolicySettings,
+          HHDSketch, HHDThreshold, HHDWindow, HeavyHitter, HeavyHitterPolicy, MaxCountPolicy};
 use l2::{DivertType, L2Manager};
-use std::sync::Mutex;
+use label::{escape_tag, LabelingManager};
+use metrics::MetricsCollector;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::Duration;
 
-pub struct HHDManager {
-    session_handler: u32,
-    max_number_of_flows: u16,
-    last_heavy_flow: Option<Flow>,
+pub const DEFAULT_HHD_SESSION: &'static str = "default";
+
+const MIN_ANALYSIS_WINDOW_IN_MILLIS: u64 = 10;
+
+// how often the counters of all learned flows get read from the sketch
+const FLOW_COUNTERS_INTERVAL_IN_SECONDS: u64 = 5;
+
+// longest the picker sleeps, so that windows of new or changed sessions end in time
+const MAX_PICKER_SLEEP_IN_MILLIS: u64 = 100;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HHDMetric {
+    Packets,
+    Bytes,
+}
+
+impl HHDMetric {
+    pub fn from_name(name: &str) -> Option<HHDMetric> {
+        match name {
+            "packets" => Some(HHDMetric::Packets),
+            "bytes" => Some(HHDMetric::Bytes),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            HHDMetric::Packets => "packets",
+            HHDMetric::Bytes => "bytes",
+        }
+    }
+
+    fn flows_counter(&self) -> FlowsCounter {
+        match *self {
+            HHDMetric::Packets => FlowsCounter::Packets,
+            HHDMetric::Bytes => FlowsCounter::Bytes,
+        }
+    }
+}
+
+#[derive(Clone, Debug, RustcEncodable)]
+pub struct HHDFlowCount {
+    pub rank: usize,
+    pub flow: Flow,
+    pub packets: u64,
+    pub bytes: u64,
+}
+
+#[derive(Clone, Debug, RustcEncodable)]
+pub struct HHDWindowResult {
+    pub session: String,
+    pub window_start: u64,
+    pub window_end: u64,
+    pub metric: String,
+    pub policy: String,
+    pub observe_only: bool,
+    pub threshold: Option<u64>,
+    pub heavy_hitters: Vec<HeavyHitter>,
+    pub change_threshold: Option<u64>,
+    pub heavy_changes: Vec<HHDFlowChange>,
+    pub diverted: bool,
+    pub number_of_flows: usize,
+    pub number_of_dropped_flows: u64,
+    pub total_packets: u64,
+    pub total_bytes: u64,
+    pub top_flows: Vec<HHDFlowCount>,
+}
+
+#[derive(Clone, Debug, RustcEncodable)]
+pub struct HHDSessionStatus {
+    pub name: String,
+    pub ports_ingress: Vec<u32>,
+    pub running: bool,
+    pub analysis_window_in_seconds: f64,
+    pub window_start: u64,
+    pub window_end: u64,
+    pub metric: String,
+    pub policy: String,
+    pub max_number_of_diverts: usize,
+    pub divert_on: bool,
+    pub observe_only: bool,
+    pub divert_type: Option<String>,
+    pub divert_ingress: Option<u32>,
+    pub divert_egress: Option<u32>,
+    pub diverted: Vec<HeavyHitter>,
+    pub number_of_results: usize,
+}
+
+// accuracy of the count-min sketch, the error bound applies to the total count of the latest windows
+#[derive(Clone, Debug, RustcEncodable)]
+pub struct HHDStatus {
+    pub sketch_rows: usize,
+    pub sketch_width: u32,
+    pub epsilon: f64,
+    pub delta: f64,
+    pub number_of_sessions: usize,
+    pub total_packets: u64,
+    pub total_bytes: u64,
+    pub error_bound_packets: f64,
+    pub error_bound_bytes: f64,
+}
+
+// counted ports, divert setup, window, policy and state of one named HHD session
+struct HHDSession {
+    counted_ports: Vec<u32>,
+    running: bool,
+    analysis_window_in_millis: u64,
+    metric: HHDMetric,
+    window_start: u64,
+    window_end: u64,
+    // sketch counters at the start of the window
+    baseline: Arc<HHDCounters>,
+    // flows dropped by the flows session up to the start of the window
+    number_of_dropped_flows: u64,
+    results: VecDeque<HHDWindowResult>,
+    policy: Box<HeavyHitterPolicy>,
+    max_number_of_diverts: usize,
+    hysteresis: HHDHysteresis,
+    change_detection: HHDChangeDetection,
+    diverted: Vec<HeavyHitter>,
     divert_on: bool,
+    observe_only: bool,
     divert_type: DivertType,
     divert_ingress_port: u32,
     divert_egress_port: u32,
 }
 
+impl HHDSession {
+    // flows counted by a session get learned in their own flows session
+    fn flows_session_name(name: &str) -> String {
+        match name {
+            DEFAULT_HHD_SESSION => HHD_SESSION_PREFIX.to_string(),
+            name => format!{"{}-{}", HHD_SESSION_PREFIX, name},
+        }
+    }
+
+    // windows end on multiples of the window length since epoch, lining up with time buckets of the database
+    fn start_window(&mut self, now: u64, baseline: Arc<HHDCounters>) {
+        self.baseline = baseline;
+        self.window_start = now;
+        self.window_end = next_window_boundary(now, self.analysis_window_in_millis);
+    }
+
+    fn is_due(&self, now: u64) -> bool {
+        self.running && !self.counted_ports.is_empty() && now >= self.window_end
+    }
+
+    fn status(&self, name: &str) -> HHDSessionStatus {
+        HHDSessionStatus {
+            name: name.to_string(),
+            ports_ingress: self.counted_ports.clone(),
+            running: self.running,
+            analysis_window_in_seconds: self.analysis_window_in_millis as f64 / 1000.0,
+            window_start: self.window_start,
+            window_end: self.window_end,
+            metric: self.metric.name().to_string(),
+            policy: self.policy.name().to_string(),
+            max_number_of_diverts: self.max_number_of_diverts,
+            divert_on: self.divert_on,
+            observe_only: self.observe_only,
+            divert_type: match self.divert_on {
+                true => Some(format!{"{:?}", self.divert_type}),
+                false => None,
+            },
+            divert_ingress: match self.divert_on {
+                true => Some(self.divert_ingress_port),
+                false => None,
+            },
+            divert_egress: match self.divert_on {
+                true => Some(self.divert_egress_port),
+                false => None,
+            },
+            diverted: self.diverted.clone(),
+            number_of_results: self.results.len(),
+        }
+    }
+}
+
+pub struct HHDManager {
+    session_handler: u32,
+    sketch: HHDSketch,
+    max_number_of_flows: u32,
+    top_k: usize,
+    results_history_size: usize,
+    sessions: HashMap<String, HHDSession>,
+    picker_running: bool,
+    // new sessions start with the configured settings
+    analysis_window_in_millis: u64,
+    max_number_of_diverts: usize,
+    hysteresis: HHDHysteresis,
+    change_detection: HHDChangeDetection,
+    policy_name: String,
+    policy_settings: HHDPolicySettings,
+    // sketch counters at the last update of the learned flows
+    flow_counters_baseline: Option<Arc<HHDCounters>>,
+}
+
 lazy_static! {
     static ref MANAGER: Mutex<HHDManager> = Mutex::new(
         HHDManager{
             session_handler: 0,
+            sketch: HHDSketch { rows: 2, width: 16384 },
             max_number_of_flows: 100,
-            last_heavy_flow: None,
-            divert_on: false,
-            divert_type: DivertType::IPSrc,
-            divert_ingress_port: 999,
-            divert_egress_port: 999,
+            top_k: 10,
+            results_history_size: 100,
+            sessions: HashMap::new(),
+            picker_running: false,
+            analysis_window_in_millis: 30000,
+            max_number_of_diverts: 10,
+            hysteresis: HHDHysteresis::new(0.0, 1, 0),
+            change_detection: HHDChangeDetection::new(None, None, false),
+            policy_name: "max-count".to_string(),
+            policy_settings: HHDPolicySettings {
+                threshold: None,
+                k: 10,
+                prefix_length: 24,
+                prefix_lengths: vec![32, 24, 16],
+                change_ratio: None,
+            },
+            flow_counters_baseline: None,
         });
 }
 
 impl HHDManager {
-    pub fn init(max_number_of_flows: u16, analysis_window_in_seconds: u16) {
-        println!("HHD Max Number of Flow set to {}", max_number_of_flows);
+    pub fn init(
+        sketch: HHDSketch,
+        max_number_of_flows: u32,
+        analysis_window_in_millis: u64,
+        top_k: usize,
+        results_history_size: usize,
+        max_number_of_diverts: usize,
+        hysteresis: HHDHysteresis,
+        change_detection: HHDChangeDetection,
+        policy_name: &str,
+        policy_settings: HHDPolicySettings,
+    ) {
+        println!("HHD Max Number of Flow set to {}, Max Number of Diverts set to {}", max_number_of_flows, max_number_of_diverts);
+        println!(
+            "HHD Sketch {} rows, width {}, error {:.6} of the total count with probability {:.4}",
+            sketch.rows,
+            sketch.width,
+            sketch.epsilon(),
+            1.0 - sketch.delta()
+        );
+        println!("HHD Policy {}", policy_name);
+        println!(
+            "HHD Switch Margin {}%, Switch after {} windows, Min Hold Time {}s",
+            hysteresis.switch_margin_percent, hysteresis.switch_after_windows, hysteresis.min_hold_time_in_seconds
+        );
+        if change_detection.is_on() {
+            println!(
+                "HHD Heavy Change Threshold {:?}, Min Ratio {:?}, Divert {}",
+                change_detection.threshold, change_detection.min_ratio, change_detection.divert
+            );
+        }
         let mut manager = MANAGER.lock().unwrap();
+        manager.hysteresis = hysteresis;
+        manager.change_detection = change_detection;
+        manager.policy_name = policy_name.to_string();
+        manager.policy_settings = policy_settings;
         manager.session_handler = HHDLayer::init();
+        manager.sketch = sketch;
         manager.max_number_of_flows = max_number_of_flows;
+        manager.max_number_of_diverts = max_number_of_diverts;
+        manager.top_k = top_k;
+        manager.results_history_size = results_history_size;
+        manager.analysis_window_in_millis = analysis_window_in_millis.max(MIN_ANALYSIS_WINDOW_IN_MILLIS);
+
+        println!(
+            "HHD Analysis Window {}ms, Top {} flows, Results History Size {}",
+            manager.analysis_window_in_millis, top_k, results_history_size
+        );
 
-        println!("HHD Analysis Window {}s", analysis_window_in_seconds);
-        let _ = thread::Builder::new().name("hhd-picker".to_string()).spawn(move || loop {
-            pick_hhd();
-            thread::sleep(Duration::from_secs(analysis_window_in_seconds as u64));
+        let _ = thread::Builder::new().name("hhd-flow-counters".to_string()).spawn(move || loop {
+            thread::sleep(Duration::from_secs(FLOW_COUNTERS_INTERVAL_IN_SECONDS));
+            update_flow_counters();
         });
     }
 
-    pub fn reset_hhd() {
-        HHDManager::reset_hhd_table();
-        FlowsManager::stop_flow_learning();
-        HHDManager::reset_counters();
+    // sessions get created with the configured settings the first time they are used
+    pub fn create_session(session_name: &str) {
+        let mut manager = MANAGER.lock().unwrap();
+        if manager.sessions.contains_key(session_name) {
+            return;
+        }
+
+        let session = HHDSession {
+            counted_ports: Vec::new(),
+            running: true,
+            analysis_window_in_millis: manager.analysis_window_in_millis,
+            metric: HHDMetric::Packets,
+            window_start: 0,
+            window_end: 0,
+            baseline: Arc::new(HHDCounters::empty()),
+            number_of_dropped_flows: 0,
+            results: VecDeque::new(),
+            policy: create_policy(&manager.policy_name, &manager.policy_settings).unwrap_or(Box::new(MaxCountPolicy)),
+            max_number_of_diverts: manager.max_number_of_diverts,
+            hysteresis: manager.hysteresis.clone(),
+            change_detection: manager.change_detection.clone(),
+            diverted: Vec::new(),
+            divert_on: false,
+            observe_only: true,
+            divert_type: DivertType::IPSrc,
+            divert_ingress_port: 999,
+            divert_egress_port: 999,
+        };
+        manager.sessions.insert(session_name.to_string(), session);
+        println!("HHD session {} created", session_name);
+    }
+
+    // stops counting and diverting of the session and removes it with its results
+    pub fn remove_session(session_name: &str) -> bool {
+        let session = match MANAGER.lock().unwrap().sessions.remove(session_name) {
+            Some(session) => session,
+            None => return false,
+        };
+
+        FlowsManager::remove_session(&HHDSession::flows_session_name(session_name));
+
+        for chassis_port_ingress in session.counted_ports.iter() {
+            FeatureManager::disable(Feature::HHD, *chassis_port_ingress);
+        }
+        if !session.diverted.is_empty() {
+            L2Manager::reset_divert_for_ingress_egress_port(session.divert_ingress_port, session.divert_egress_port);
+        };
+
+        // with other sessions still counting their windows go on, their baselines keep them apart
+        let (session_handler, sketch, counting) = {
+            let manager = MANAGER.lock().unwrap();
+            let counting = manager.sessions.values().any(|session| !session.counted_ports.is_empty());
+            (manager.session_handler, manager.sketch, counting)
+        };
+        if !counting {
+            HHDLayer::reset_all_counters(session_handler, sketch.rows, sketch.width);
+        }
+
+        println!("HHD session {} removed", session_name);
+        true
+    }
+
+    pub fn remove_all_sessions() {
+        let session_names: Vec<String> = MANAGER.lock().unwrap().sessions.keys().cloned().collect();
+        for session_name in session_names {
+            HHDManager::remove_session(&session_name);
+        }
     }
 
-    pub fn set_hhd(chassis_port_ingress: u32) {
+    // claims all ports or none, the session only gets created once its ports are claimed
+    pub fn set_hhd(session_name: &str, chassis_ports_ingress: &Vec<u32>) -> Result<(), String> {
+        if chassis_ports_ingress.is_empty() {
+            HHDManager::create_session(session_name);
+            return Ok(());
+        }
+
+        let max_number_of_flows = MANAGER.lock().unwrap().max_number_of_flows;
+        FlowsManager::start_flow_learning(&HHDSession::flows_session_name(session_name), chassis_ports_ingress, max_number_of_flows)?;
+
+        HHDManager::create_session(session_name);
+        if let Some(session) = MANAGER.lock().unwrap().sessions.get(session_name) {
+            FlowsManager::set_eviction_counter(&HHDSession::flows_session_name(session_name), session.metric.flows_counter());
+        }
+        for chassis_port_ingress in chassis_ports_ingress {
+            FeatureManager::enable(Feature::HHD, *chassis_port_ingress);
+        }
+        let starting = match MANAGER.lock().unwrap().sessions.get(session_name) {
+            Some(session) => session.counted_ports.is_empty(),
+            None => false,
+        };
+        let baseline = match starting {
+            true => Some(sync_counters()),
+            false => None,
+        };
+        if let Some(session) = MANAGER.lock().unwrap().sessions.get_mut(session_name) {
+            if let Some(baseline) = baseline {
+                session.start_window(current_time_in_millis(), baseline);
+            }
+            for chassis_port_ingress in chassis_ports_ingress {
+                if !session.counted_ports.contains(chassis_port_ingress) {
+                    session.counted_ports.push(*chassis_port_ingress);
+                }
+            }
+        }
+        start_picker();
+
+        println!("HHD session {} turned on on {:?}", session_name, chassis_ports_ingress);
+        Ok(())
+    }
+
+    pub fn verify_analysis_window(analysis_window_in_millis: u64) -> Result<(), String> {
+        match analysis_window_in_millis < MIN_ANALYSIS_WINDOW_IN_MILLIS {
+            true => Err(format!("analysis window has to be at least {}ms", MIN_ANALYSIS_WINDOW_IN_MILLIS)),
+            false => Ok(()),
+        }
+    }
+
+    // removing a session resets the diverts of its port pair, sessions can't share one
+    pub fn verify_divert_ports(session_name: &str, chassis_port_ingress: u32, chassis_port_egress: u32) -> Result<(), String> {
         let manager = MANAGER.lock().unwrap();
+        for (name, session) in manager.sessions.iter() {
+            if name != session_name && session.divert_on && session.divert_ingress_port == chassis_port_ingress
+                && session.divert_egress_port == chassis_port_egress
+            {
+                return Err(format!(
+                    "Divert from {} to {} is already used by HHD session {}",
+                    chassis_port_ingress, chassis_port_egress, name
+                ));
+            }
+        }
+        Ok(())
+    }
 
-        FlowsManager::start_flow_learning(chassis_port_ingress, manager.max_number_of_flows);
+    // the current window ends on the next boundary of the new length
+    pub fn set_analysis_window(session_name: &str, analysis_window_in_millis: u64) -> Result<(), String> {
+        HHDManager::verify_analysis_window(analysis_window_in_millis)?;
 
-        let dev_port_ingress = HWManager::convert_chassis_port_to_dev_port(&chassis_port_ingress);
-        HHDLayer::set_hhd(manager.session_handler, dev_port_ingress as u16);
+        HHDManager::create_session(session_name);
+        if let Some(session) = MANAGER.lock().unwrap().sessions.get_mut(session_name) {
+            session.analysis_window_in_millis = analysis_window_in_millis;
+            session.window_end = next_window_boundary(current_time_in_millis().max(session.window_start), analysis_window_in_millis);
+        }
+        println!("HHD session {} analysis window set to {}ms", session_name, analysis_window_in_millis);
+        Ok(())
+    }
 
-        println!("HHD turned on on {}", chassis_port_ingress);
+    // a stopped session keeps counting and its diverts stay, only its windows don't get analyzed,
+    // on start a new window begins, counting from the current counters
+    pub fn set_running(session_name: &str, running: bool) {
+        HHDManager::create_session(session_name);
+        let started = match MANAGER.lock().unwrap().sessions.get_mut(session_name) {
+            Some(session) => {
+                let started = running && !session.running;
+                session.running = running;
+                started
+            }
+            None => false,
+        };
+
+        if started {
+            let baseline = sync_counters();
+            if let Some(session) = MANAGER.lock().unwrap().sessions.get_mut(session_name) {
+                session.start_window(current_time_in_millis(), baseline);
+            }
+            start_picker();
+        }
+        println!("HHD session {} analysis {}", session_name, if running { "running" } else { "stopped" });
     }
 
-    pub fn run_hhd_divert(divert_ingress: u32, divert_egress: u32, divert_type: DivertType) {
-        let mut manager = MANAGER.lock().unwrap();
-        manager.divert_on = true;
-        manager.divert_type = divert_type;
-        manager.divert_ingress_port = divert_ingress;
-        manager.divert_egress_port = divert_egress;
+    pub fn set_metric(session_name: &str, metric: HHDMetric) {
+        HHDManager::create_session(session_name);
+        if let Some(session) = MANAGER.lock().unwrap().sessions.get_mut(session_name) {
+            if session.metric != metric {
+                session.change_detection.reset();
+            }
+            session.metric = metric;
+        }
+        FlowsManager::set_eviction_counter(&HHDSession::flows_session_name(session_name), metric.flows_counter());
+        println!("HHD session {} metric set to {:?}", session_name, metric);
+    }
 
-        println!("HHD auto divert turned on on {} -> {}", divert_ingress, divert_egress);
+    // the policy picks the heavy hitters of each window, at most max_number_of_diverts of them get diverted
+    pub fn set_policy(session_name: &str, policy: Box<HeavyHitterPolicy>) {
+        HHDManager::create_session(session_name);
+        println!("HHD session {} policy set to {}", session_name, policy.name());
+        if let Some(session) = MANAGER.lock().unwrap().sessions.get_mut(session_name) {
+            session.policy = policy;
+            session.hysteresis.reset();
+        }
     }
 
-    pub fn reset_counters() {
-        let session_handler = MANAGER.lock().unwrap().session_handler;
-        for flow_to_delete in FlowsManager::get_learned_flows() {
-            HHDLayer::reset_counters(session_handler, &vec![flow_to_delete.hash1, flow_to_delete.hash2]);
+    pub fn set_max_number_of_diverts(session_name: &str, max_number_of_diverts: usize) {
+        HHDManager::create_session(session_name);
+        if let Some(session) = MANAGER.lock().unwrap().sessions.get_mut(session_name) {
+            session.max_number_of_diverts = max_number_of_diverts;
         }
+        println!("HHD session {} max number of diverts set to {}", session_name, max_number_of_diverts);
+    }
 
-        MANAGER.lock().unwrap().last_heavy_flow = None;
+    pub fn get_top_k() -> usize {
+        MANAGER.lock().unwrap().top_k
     }
 
-    pub fn reset_hhd_table() {
-        let mut manager = MANAGER.lock().unwrap();
-        HHDLayer::reset_hhd_table(manager.session_handler);
-        if manager.divert_on {
-            manager.divert_on = false;
-            L2Manager::reset_divert_for_ingress_egress_port(manager.divert_ingress_port, manager.divert_egress_port);
-        };
+    pub fn set_hysteresis(
+        session_name: &str,
+        switch_margin_percent: Option<f64>,
+        switch_after_windows: Option<u32>,
+        min_hold_time_in_seconds: Option<u32>,
+    ) {
+        HHDManager::create_session(session_name);
+        if let Some(session) = MANAGER.lock().unwrap().sessions.get_mut(session_name) {
+            if let Some(switch_margin_percent) = switch_margin_percent {
+                session.hysteresis.switch_margin_percent = switch_margin_percent;
+            }
+            if let Some(switch_after_windows) = switch_after_windows {
+                session.hysteresis.switch_after_windows = switch_after_windows;
+            }
+            if let Some(min_hold_time_in_seconds) = min_hold_time_in_seconds {
+                session.hysteresis.min_hold_time_in_seconds = min_hold_time_in_seconds;
+            }
+        }
+    }
+
+    // heavy changes get reported and labeled every window, diverted only if divert is set,
+    // the counts of the previous window are kept
+    pub fn set_change_detection(session_name: &str, threshold: Option<HHDThreshold>, min_ratio: Option<f64>, divert: bool) {
+        HHDManager::create_session(session_name);
+        if let Some(session) = MANAGER.lock().unwrap().sessions.get_mut(session_name) {
+            session.change_detection.threshold = threshold;
+            session.change_detection.min_ratio = min_ratio;
+            session.change_detection.divert = divert;
+        }
+        println!(
+            "HHD session {} heavy change threshold set to {:?}, min ratio {:?}, divert {}",
+            session_name, threshold, min_ratio, divert
+        );
+    }
+
+    // in observe only mode heavy flows get picked and reported but the divert table stays untouched
+    pub fn run_hhd_divert(session_name: &str, divert_ingress: u32, divert_egress: u32, divert_type: DivertType, observe_only: bool) {
+        HHDManager::create_session(session_name);
+        let mut installed_pair = None;
+        if let Some(session) = MANAGER.lock().unwrap().sessions.get_mut(session_name) {
+            // the diverts installed for the previous setup go, the next window installs them for the new one,
+            // observing sessions don't keep any
+            let changed = session.divert_type != divert_type || session.divert_ingress_port != divert_ingress
+                || session.divert_egress_port != divert_egress;
+            if (changed || observe_only) && !session.diverted.is_empty() {
+                installed_pair = Some((session.divert_ingress_port, session.divert_egress_port));
+                session.diverted.clear();
+            }
+            session.divert_on = true;
+            session.observe_only = observe_only;
+            session.divert_type = divert_type;
+            session.divert_ingress_port = divert_ingress;
+            session.divert_egress_port = divert_egress;
+        }
+        if let Some((installed_ingress, installed_egress)) = installed_pair {
+            L2Manager::reset_divert_for_ingress_egress_port(installed_ingress, installed_egress);
+        }
+
+        match observe_only {
+            true => println!("HHD session {} observing, would divert on {} -> {}", session_name, divert_ingress, divert_egress),
+            false => println!("HHD session {} auto divert turned on on {} -> {}", session_name, divert_ingress, divert_egress),
+        }
+    }
+
+    pub fn get_sessions() -> Vec<HHDSessionStatus> {
+        let manager = MANAGER.lock().unwrap();
+        let mut result: Vec<HHDSessionStatus> = manager.sessions.iter().map(|(name, session)| session.status(name)).collect();
+        result.sort_by(|a, b| a.name.cmp(&b.name));
+        result
+    }
+
+    pub fn get_session(session_name: &str) -> Option<HHDSessionStatus> {
+        MANAGER.lock().unwrap().sessions.get(session_name).map(|session| session.status(session_name))
+    }
+
+    // sessions share the sketch, so all their counts add to its error
+    pub fn get_status() -> HHDStatus {
+        let manager = MANAGER.lock().unwrap();
+        let latest_results: Vec<&HHDWindowResult> = manager.sessions.values().filter_map(|session| session.results.back()).collect();
+        let total_packets: u64 = latest_results.iter().map(|result| result.total_packets).sum();
+        let total_bytes: u64 = latest_results.iter().map(|result| result.total_bytes).sum();
+
+        HHDStatus {
+            sketch_rows: manager.sketch.rows,
+            sketch_width: manager.sketch.width,
+            epsilon: manager.sketch.epsilon(),
+            delta: manager.sketch.delta(),
+            number_of_sessions: manager.sessions.len(),
+            total_packets: total_packets,
+            total_bytes: total_bytes,
+            error_bound_packets: manager.sketch.epsilon() * total_packets as f64,
+            error_bound_bytes: manager.sketch.epsilon() * total_bytes as f64,
+        }
+    }
+
+    pub fn get_results(session_name: &str) -> Option<Vec<HHDWindowResult>> {
+        MANAGER.lock().unwrap().sessions.get(session_name).map(|session| session.results.iter().cloned().collect())
+    }
+
+    pub fn get_latest_result(session_name: &str) -> Option<HHDWindowResult> {
+        match MANAGER.lock().unwrap().sessions.get(session_name) {
+            Some(session) => session.results.back().cloned(),
+            None => None,
+        }
     }
 }
 
-fn pick_hhd() {
-    if !MANAGER.lock().unwrap().divert_on {
-        return;
+fn next_window_boundary(now: u64, analysis_window_in_millis: u64) -> u64 {
+    (now / analysis_window_in_millis + 1) * analysis_window_in_millis
+}
+
+// one hardware sync of the whole sketch, shared by all sessions whose window ends
+fn sync_counters() -> Arc<HHDCounters> {
+    let (session_handler, sketch) = {
+        let manager = MANAGER.lock().unwrap();
+        (manager.session_handler, manager.sketch)
     };
+    Arc::new(HHDCounters::new(HHDLayer::sync_counters(session_handler, sketch.rows, sketch.width)))
+}
 
+// packets and bytes of all learned flows since the last update, flows keep their totals and last seen time from these
+fn update_flow_counters() {
     let learned_flows = FlowsManager::get_learned_flows();
-    let session_handler = MANAGER.lock().unwrap().session_handler;
+    if learned_flows.is_empty() {
+        MANAGER.lock().unwrap().flow_counters_baseline = None;
+        return;
+    }
 
-    let mut largest_result = 0;
-    let mut largest_flow: Option<Flow> = None;
+    let counters = sync_counters();
+    let (baseline, sketch) = {
+        let mut manager = MANAGER.lock().unwrap();
+        (manager.flow_counters_baseline.replace(counters.clone()), manager.sketch)
+    };
+    let baseline = match baseline {
+        Some(baseline) => baseline,
+        None => return,
+    };
 
-    for learned_flow in learned_flows {
-        let result = HHDLayer::retrieve_smallest_packet_count(session_handler, &vec![learned_flow.hash1, learned_flow.hash2]);
+    let flow_counters: Vec<(Flow, u64, u64)> = learned_flows
+        .into_iter()
+        .map(|learned_flow| {
+            let (packets, bytes) = counters.estimate(&baseline, &sketch.hashes(&learned_flow));
+            (learned_flow, packets, bytes)
+        })
+        .collect();
+    FlowsManager::update_flow_counters(&flow_counters);
+}
 
-        if result > largest_result {
-            largest_result = result;
-            largest_flow = Some(learned_flow.clone());
+// the picker only runs while some session analyzes windows and stops once none is left
+fn start_picker() {
+    {
+        let mut manager = MANAGER.lock().unwrap();
+        if manager.picker_running {
+            return;
         }
-        // println!("Flow: {:?} packet counts: {}", learned_flow, result);
+        manager.picker_running = true;
+    }
+
+    println!("HHD picker started");
+    let _ = thread::Builder::new().name("hhd-picker".to_string()).spawn(move || loop {
+        let now = current_time_in_millis();
+        let (due_sessions, next_window_end) = {
+            let mut manager = MANAGER.lock().unwrap();
+            let next_window_end = manager
+                .sessions
+                .values()
+                .filter(|session| session.running && !session.counted_ports.is_empty())
+                .map(|session| session.window_end)
+                .min();
+            if next_window_end.is_none() {
+                manager.picker_running = false;
+                println!("HHD picker stopped");
+                return;
+            }
+            let due_sessions: Vec<String> = manager
+                .sessions
+                .iter()
+                .filter(|&(_, session)| session.is_due(now))
+                .map(|(name, _)| name.clone())
+                .collect();
+            (due_sessions, next_window_end.unwrap())
+        };
+
+        if !due_sessions.is_empty() {
+            let counters = sync_counters();
+            for session_name in due_sessions.iter() {
+                pick_hhd(session_name, counters.clone());
+            }
+        } else {
+            let sleep_in_millis = next_window_end.saturating_sub(now).min(MAX_PICKER_SLEEP_IN_MILLIS);
+            thread::sleep(Duration::from_millis(sleep_in_millis));
+        }
+    });
+}
+
+// analyzes every window while the session counts, diverts only if divert is on and not observing
+fn pick_hhd(session_name: &str, counters: Arc<HHDCounters>) {
+    let now = current_time_in_millis();
+    let (baseline, sketch, top_k, metric, divert_type, max_number_of_diverts, window_start, window_end, counted_ports) = {
+        let manager = MANAGER.lock().unwrap();
+        match manager.sessions.get(session_name) {
+            // a late picker closes the window on the last boundary passed
+            Some(session) => (
+                session.baseline.clone(),
+                manager.sketch,
+                manager.top_k,
+                session.metric,
+                session.divert_type,
+                session.max_number_of_diverts,
+                session.window_start,
+                now / session.analysis_window_in_millis * session.analysis_window_in_millis,
+                session.counted_ports.clone(),
+            ),
+            None => return,
+        }
+    };
+    let flows_session_name = HHDSession::flows_session_name(session_name);
+    let learned_flows = FlowsManager::get_learned_flows_for_session(&flows_session_name).unwrap_or(Vec::new());
+
+    let mut flow_counters = Vec::with_capacity(learned_flows.len());
+    for learned_flow in learned_flows {
+        let (packets, bytes) = counters.estimate(&baseline, &sketch.hashes(&learned_flow));
+        // println!("Flow: {:?} packet counts: {} byte counts: {}", learned_flow, packets, bytes);
+        flow_counters.push((learned_flow, packets, bytes));
+    }
+
+    let window = HHDWindow {
+        metric: metric,
+        divert_type: divert_type,
+        window_start: window_start,
+        window_end: window_end,
+        port_rate: retrieve_ports_rate(&counted_ports, metric),
+        flows: rank_flows(&flow_counters, metric),
+    };
+
+    let (policy_name, change_candidates, policy_candidates, threshold, heavy_changes, change_threshold) = {
+        let mut manager = MANAGER.lock().unwrap();
+        let session = match manager.sessions.get_mut(session_name) {
+            Some(session) => session,
+            None => return,
+        };
+        // diverted heavy changes take their divert entries first, the policy selects for the entries left
+        let heavy_changes = session.change_detection.detect(&window);
+        let change_candidates = match session.change_detection.divert {
+            true => {
+                let heavy_hitters = heavy_changes.iter().filter_map(|change| HeavyHitter::for_change(&window, change)).collect();
+                merge_heavy_hitters(&window, heavy_hitters)
+            }
+            false => Vec::new(),
+        };
+        session.policy.set_max_number_of_heavy_hitters(max_number_of_diverts.saturating_sub(change_candidates.len()));
+        let policy_candidates = session.policy.select(&window);
+        (
+            session.policy.name(),
+            change_candidates,
+            policy_candidates,
+            session.policy.threshold(),
+            heavy_changes,
+            session.change_detection.limit(),
+        )
+    };
+
+    for change in heavy_changes.iter() {
+        println!(
+            "Heavy change {:?} from {} to {} {}, ratio {:?}",
+            change.flow,
+            change.previous_count,
+            change.count,
+            metric.name(),
+            change.ratio
+        );
+        LabelingManager::label_heavy_change(
+            metric.name(),
+            format!{"{}:{}->{}:{}", change.flow.src_addr, change.flow.src_port, change.flow.dst_addr, change.flow.dst_port},
+            change.flow.ipv4_protocol,
+            change.delta,
+            change.ratio,
+        );
     }
 
-    if largest_flow.is_some() {
+    // diverted heavy changes come first, then the heavy hitters of the policy, the limit counts divert entries
+    let mut candidates = change_candidates;
+    candidates.extend(policy_candidates);
+    let mut candidates = merge_heavy_hitters(&window, candidates);
+    candidates.truncate(max_number_of_diverts);
+
+    // in windows without any traffic the heavy hitters stay selected
+    let total: u64 = window.flows.iter().map(|flow_count| window.count(flow_count)).sum();
+    let number_of_dropped_flows = FlowsManager::get_number_of_dropped_flows(&flows_session_name);
+    let (heavy_hitters, divert_on, observe_only, number_of_dropped_flows_in_window) = {
+        let mut manager = MANAGER.lock().unwrap();
+        let session = match manager.sessions.get_mut(session_name) {
+            Some(session) => session,
+            None => return,
+        };
+        let heavy_hitters = match total {
+            0 => session.hysteresis.selected(),
+            _ => session.hysteresis.select(candidates, &window),
+        };
+        // a restarted flows session counts from 0 again
+        let number_of_dropped_flows_in_window = match number_of_dropped_flows >= session.number_of_dropped_flows {
+            true => number_of_dropped_flows - session.number_of_dropped_flows,
+            false => number_of_dropped_flows,
+        };
+        session.number_of_dropped_flows = number_of_dropped_flows;
+        (heavy_hitters, session.divert_on, session.observe_only, number_of_dropped_flows_in_window)
+    };
+
+    if !heavy_hitters.is_empty() {
         println!(
-            "Number of flows seen: {}, max set to: {}",
-            FlowsManager::get_current_number_of_flows(),
+            "HHD session {}, number of flows seen: {}, max set to: {}",
+            session_name,
+            FlowsManager::get_current_number_of_flows(&flows_session_name),
             MANAGER.lock().unwrap().max_number_of_flows
         );
+    }
+    for heavy_hitter in heavy_hitters.iter() {
+        println!(
+            "Heavy hitter {}/{} with {} packets, {} bytes in {} flows",
+            heavy_hitter.address,
+            heavy_hitter.prefix_length,
+            heavy_hitter.packets,
+            heavy_hitter.bytes,
+            heavy_hitter.flows.len()
+        );
+        for flow in heavy_hitter.flows.iter().filter(|flow| flow.collision == FlowCollision::Full) {
+            println!("Heavy flow {:?} shares its counters with other flows, confidence {}", flow, flow.confidence);
+        }
+    }
 
-        let flow = largest_flow.unwrap();
-        println!("Largest flow from {:?} to {:?}, details {:?}", flow.src_addr, flow.dst_addr, flow);
-
-        if MANAGER.lock().unwrap().divert_on {
-            let divert_ingress_port = MANAGER.lock().unwrap().divert_ingress_port;
-            let divert_egress_port = MANAGER.lock().unwrap().divert_egress_port;
-            let divert_type = MANAGER.lock().unwrap().divert_type.clone();
-            let last_heavy_flow = MANAGER.lock().unwrap().last_heavy_flow.clone();
-
-            if last_heavy_flow.is_none() || (last_heavy_flow.is_some() && last_heavy_flow.unwrap() != flow) {
-                L2Manager::reset_divert_for_ingress_egress_port(divert_ingress_port, divert_egress_port);
-
-                L2Manager::set_divert(
-                    divert_type,
-                    divert_ingress_port,
-                    divert_egress_port,
-                    &match divert_type {
-                        DivertType::IPDest => flow.dst_addr.clone(),
-                        DivertType::IPSrc => flow.src_addr.clone(),
-                    },
-                    32,
-                    true,
-                );
-            }
+    let mut diverted = false;
+    if divert_on && !observe_only {
+        divert_heavy_hitters(session_name, &heavy_hitters);
+        diverted = !heavy_hitters.is_empty();
+    }
+
+    let mut top_flows = window.flows.clone();
+    top_flows.truncate(top_k);
+    // flows stay learned until they age out, the next window counts from these counters
+    record_result(
+        session_name,
+        counters,
+        HHDWindowResult {
+            session: session_name.to_string(),
+            window_start: window_start,
+            window_end: window_end,
+            metric: metric.name().to_string(),
+            policy: policy_name.to_string(),
+            observe_only: observe_only,
+            threshold: threshold,
+            heavy_hitters: heavy_hitters,
+            change_threshold: change_threshold,
+            heavy_changes: heavy_changes,
+            diverted: diverted,
+            number_of_flows: window.flows.len(),
+            number_of_dropped_flows: number_of_dropped_flows_in_window,
+            total_packets: window.flows.iter().map(|flow_count| flow_count.packets).sum(),
+            total_bytes: window.flows.iter().map(|flow_count| flow_count.bytes).sum(),
+            top_flows: top_flows,
+        },
+    );
+}
 
-            MANAGER.lock().unwrap().last_heavy_flow = Some(flow);
+// packets or bits per second received on the counted ports
+fn retrieve_ports_rate(counted_ports: &Vec<u32>, metric: HHDMetric) -> Option<f64> {
+    let mut port_rate = None;
+    for chassis_port in counted_ports {
+        if let Some(metrics) = MetricsCollector::get_port_stats_for_port(*chassis_port) {
+            let rate = match metric {
+                HHDMetric::Packets => metrics.packets_in_per_second,
+                HHDMetric::Bytes => metrics.bits_in_per_second,
+            };
+            port_rate = Some(port_rate.unwrap_or(0.0) + rate);
         }
+    }
+    port_rate
+}
+
+// divert rules only get rewritten if the set of heavy hitters changed
+fn divert_heavy_hitters(session_name: &str, heavy_hitters: &Vec<HeavyHitter>) {
+    let (divert_ingress_port, divert_egress_port, divert_type, diverted) = match MANAGER.lock().unwrap().sessions.get(session_name) {
+        Some(session) => (session.divert_ingress_port, session.divert_egress_port, session.divert_type, session.diverted.clone()),
+        None => return,
     };
 
-    let learned_flows = FlowsManager::get_learned_flows_and_reset();
+    if diverted.len() == heavy_hitters.len() && heavy_hitters.iter().all(|heavy_hitter| diverted.contains(heavy_hitter)) {
+        return;
+    }
+
+    L2Manager::reset_divert_for_ingress_egress_port(divert_ingress_port, divert_egress_port);
+    for heavy_hitter in heavy_hitters {
+        L2Manager::set_divert(
+            divert_type,
+            divert_ingress_port,
+            divert_egress_port,
+            &heavy_hitter.address,
+            heavy_hitter.prefix_length,
+            true,
+        );
+    }
+
+    if let Some(session) = MANAGER.lock().unwrap().sessions.get_mut(session_name) {
+        session.diverted = heavy_hitters.clone();
+    }
+}
 
-    for flow_to_delete in learned_flows {
-        HHDLayer::reset_counters(session_handler, &vec![flow_to_delete.hash1, flow_to_delete.hash2]);
+// all flows, largest first
+fn rank_flows(flow_counters: &Vec<(Flow, u64, u64)>, metric: HHDMetric) -> Vec<HHDFlowCount> {
+    let mut ranked_flows: Vec<HHDFlowCount> = flow_counters
+        .iter()
+        .map(|&(ref flow, packets, bytes)| HHDFlowCount {
+            rank: 0,
+            flow: flow.clone(),
+            packets: packets,
+            bytes: bytes,
+        })
+        .collect();
+    ranked_flows.sort_by(|a, b| match metric {
+        HHDMetric::Packets => b.packets.cmp(&a.packets),
+        HHDMetric::Bytes => b.bytes.cmp(&a.bytes),
+    });
+    for (index, ranked_flow) in ranked_flows.iter_mut().enumerate() {
+        ranked_flow.rank = index + 1;
+    }
+    ranked_flows
+}
+
+fn record_result(session_name: &str, counters: Arc<HHDCounters>, result: HHDWindowResult) {
+    EventManager::publish(EventType::HHDWindow, &result);
+    LabelingManager::write_points(result_to_points(&result));
+
+    let mut manager = MANAGER.lock().unwrap();
+    let results_history_size = manager.results_history_size;
+    if let Some(session) = manager.sessions.get_mut(session_name) {
+        session.window_start = result.window_end;
+        session.baseline = counters;
+        session.window_end = next_window_boundary(result.window_end.max(current_time_in_millis()), session.analysis_window_in_millis);
+
+        if results_history_size == 0 {
+            return;
+        }
+        while session.results.len() >= results_history_size {
+            session.results.pop_front();
+        }
+        session.results.push_back(result);
+    }
+}
+
+// InfluxDB line protocol, one point for the window and one per top flow, stamped with the end of the window
+fn result_to_points(result: &HHDWindowResult) -> Vec<(String, u64)> {
+    let timestamp = result.window_end * 1000000;
+    let tags = format!{"session={},metric={}", escape_tag(&result.session), result.metric};
+
+    let mut points = Vec::with_capacity(result.top_flows.len() + 1);
+    let window_point = format!{
+        "hhd_window,{} flows={}i,dropped_flows={}i,packets={}i,bytes={}i,heavy_hitters={}i,heavy_changes={}i",
+        tags,
+        result.number_of_flows,
+        result.number_of_dropped_flows,
+        result.total_packets,
+        result.total_bytes,
+        result.heavy_hitters.len(),
+        result.heavy_changes.len()
+    };
+    points.push((window_point, timestamp));
+    for flow_count in result.top_flows.iter() {
+        let flow_point = format!{
+            "hhd_flow,{},ingress_port={},src_addr={},src_port={},dst_addr={},dst_port={},protocol={} rank={}i,packets={}i,bytes={}i",
+            tags,
+            flow_count.flow.ingress_port,
+            flow_count.flow.src_addr,
+            flow_count.flow.src_port,
+            flow_count.flow.dst_addr,
+            flow_count.flow.dst_port,
+            flow_count.flow.ipv4_protocol,
+            flow_count.rank,
+            flow_count.packets,
+            flow_count.bytes
+        };
+        points.push((flow_point, timestamp));
     }
+    points
 }
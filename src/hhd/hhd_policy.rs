@@ -0,0 +1,423 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2018 AT&T. All other rights reserved.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+use flows::Flow;
+use hhd::{HHDChangeDetection, HHDFlowChange, HHDFlowCount, HHDMetric, HHDThreshold};
+use l2::DivertType;
+use std::collections::HashMap;
+use std::net::Ipv4Addr;
+
+// what a policy gets to see of an analysis window
+pub struct HHDWindow {
+    pub metric: HHDMetric,
+    pub divert_type: DivertType,
+    pub window_start: u64,
+    pub window_end: u64,
+    // packets or bits per second received on the counted ports, None until measured
+    pub port_rate: Option<f64>,
+    // all counted flows, largest first
+    pub flows: Vec<HHDFlowCount>,
+}
+
+impl HHDWindow {
+    pub fn count(&self, flow_count: &HHDFlowCount) -> u64 {
+        match self.metric {
+            HHDMetric::Packets => flow_count.packets,
+            HHDMetric::Bytes => flow_count.bytes,
+        }
+    }
+
+    // the address that gets diverted, source or destination
+    pub fn address(&self, flow: &Flow) -> u32 {
+        match self.divert_type {
+            DivertType::IPSrc => flow.src_addr_int,
+            DivertType::IPDest => flow.dst_addr_int,
+        }
+    }
+
+    pub fn count_for(&self, heavy_hitter: &HeavyHitter) -> u64 {
+        self.flows
+            .iter()
+            .filter(|flow_count| heavy_hitter.covers(self.address(&flow_count.flow)))
+            .map(|flow_count| self.count(flow_count))
+            .sum()
+    }
+}
+
+// an address or prefix to divert and the flows behind it
+#[derive(Clone, Debug, RustcEncodable)]
+pub struct HeavyHitter {
+    pub address: String,
+    pub address_int: u32,
+    pub prefix_length: u16,
+    pub packets: u64,
+    pub bytes: u64,
+    pub flows: Vec<Flow>,
+}
+
+impl PartialEq for HeavyHitter {
+    fn eq(&self, other: &HeavyHitter) -> bool {
+        self.address_int == other.address_int && self.prefix_length == other.prefix_length
+    }
+}
+
+impl HeavyHitter {
+    pub fn for_flow(window: &HHDWindow, flow_count: &HHDFlowCount) -> HeavyHitter {
+        HeavyHitter::for_prefix(window.address(&flow_count.flow), 32, vec![flow_count])
+    }
+
+    pub fn for_change(window: &HHDWindow, change: &HHDFlowChange) -> Option<HeavyHitter> {
+        window
+            .flows
+            .iter()
+            .find(|flow_count| flow_count.flow == change.flow)
+            .map(|flow_count| HeavyHitter::for_flow(window, flow_count))
+    }
+
+    pub fn for_prefix(address_int: u32, prefix_length: u16, flow_counts: Vec<&HHDFlowCount>) -> HeavyHitter {
+        let network = address_int & prefix_mask(prefix_length);
+        HeavyHitter {
+            address: Ipv4Addr::from(network).to_string(),
+            address_int: network,
+            prefix_length: prefix_length,
+            packets: flow_counts.iter().map(|flow_count| flow_count.packets).sum(),
+            bytes: flow_counts.iter().map(|flow_count| flow_count.bytes).sum(),
+            flows: flow_counts.iter().map(|flow_count| flow_count.flow.clone()).collect(),
+        }
+    }
+
+    pub fn covers(&self, address_int: u32) -> bool {
+        address_int & prefix_mask(self.prefix_length) == self.address_int
+    }
+}
+
+// heavy hitters with the same address and prefix length share one divert entry, their flows get counted together
+pub fn merge_heavy_hitters(window: &HHDWindow, heavy_hitters: Vec<HeavyHitter>) -> Vec<HeavyHitter> {
+    let mut merged: Vec<HeavyHitter> = Vec::new();
+    for heavy_hitter in heavy_hitters {
+        match merged.iter().position(|other| *other == heavy_hitter) {
+            Some(index) => {
+                let mut flows = merged[index].flows.clone();
+                for flow in heavy_hitter.flows {
+                    if !flows.contains(&flow) {
+                        flows.push(flow);
+                    }
+                }
+                let flow_counts = window.flows.iter().filter(|flow_count| flows.contains(&flow_count.flow)).collect();
+                merged[index] = HeavyHitter::for_prefix(heavy_hitter.address_int, heavy_hitter.prefix_length, flow_counts);
+            }
+            None => merged.push(heavy_hitter),
+        }
+    }
+    merged
+}
+
+pub fn prefix_mask(prefix_length: u16) -> u32 {
+    match prefix_length {
+        0 => 0,
+        prefix_length if prefix_length >= 32 => u32::max_value(),
+        prefix_length => u32::max_value() << (32 - prefix_length as u32),
+    }
+}
+
+// picks the heavy hitters of each analysis window, HHDManager diverts them
+pub trait HeavyHitterPolicy: Send {
+    fn name(&self) -> &'static str;
+
+    // heavy hitters of the window, most important first
+    fn select(&mut self, window: &HHDWindow) -> Vec<HeavyHitter>;
+
+    // packet or byte count a heavy hitter had to reach in the last window, if the policy uses one
+    fn threshold(&self) -> Option<u64> {
+        None
+    }
+
+    // only this many heavy hitters get diverted, the heavy hitters beyond are cut off unless the policy selects with it in mind
+    fn set_max_number_of_heavy_hitters(&mut self, _max_number_of_heavy_hitters: usize) {}
+}
+
+// the single largest flow
+pub struct MaxCountPolicy;
+
+impl HeavyHitterPolicy for MaxCountPolicy {
+    fn name(&self) -> &'static str {
+        "max-count"
+    }
+
+    fn select(&mut self, window: &HHDWindow) -> Vec<HeavyHitter> {
+        window
+            .flows
+            .iter()
+            .filter(|flow_count| window.count(flow_count) > 0)
+            .take(1)
+            .map(|flow_count| HeavyHitter::for_flow(window, flow_count))
+            .collect()
+    }
+}
+
+// the k largest flows
+pub struct TopKPolicy {
+    pub k: usize,
+}
+
+impl HeavyHitterPolicy for TopKPolicy {
+    fn name(&self) -> &'static str {
+        "top-k"
+    }
+
+    fn select(&mut self, window: &HHDWindow) -> Vec<HeavyHitter> {
+        window
+            .flows
+            .iter()
+            .filter(|flow_count| window.count(flow_count) > 0)
+            .take(self.k)
+            .map(|flow_count| HeavyHitter::for_flow(window, flow_count))
+            .collect()
+    }
+}
+
+// every flow crossing the threshold
+pub struct ThresholdPolicy {
+    pub threshold: HHDThreshold,
+    limit: Option<u64>,
+}
+
+impl ThresholdPolicy {
+    pub fn new(threshold: HHDThreshold) -> ThresholdPolicy {
+        ThresholdPolicy {
+            threshold: threshold,
+            limit: None,
+        }
+    }
+}
+
+impl HeavyHitterPolicy for ThresholdPolicy {
+    fn name(&self) -> &'static str {
+        "threshold"
+    }
+
+    fn select(&mut self, window: &HHDWindow) -> Vec<HeavyHitter> {
+        self.limit = self.threshold
+            .limit_for_window(window.metric, window.window_end.saturating_sub(window.window_start), window.port_rate);
+        match self.limit {
+            Some(limit) => window
+                .flows
+                .iter()
+                .filter(|flow_count| window.count(flow_count) > 0 && window.count(flow_count) >= limit)
+                .map(|flow_count| HeavyHitter::for_flow(window, flow_count))
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    fn threshold(&self) -> Option<u64> {
+        self.limit
+    }
+}
+
+// flows that grew by at least the threshold, and by the ratio if set, since the previous window
+pub struct HeavyChangePolicy {
+    detection: HHDChangeDetection,
+}
+
+impl HeavyChangePolicy {
+    pub fn new(threshold: Option<HHDThreshold>, min_ratio: Option<f64>) -> HeavyChangePolicy {
+        HeavyChangePolicy {
+            detection: HHDChangeDetection::new(threshold, min_ratio, true),
+        }
+    }
+}
+
+impl HeavyHitterPolicy for HeavyChangePolicy {
+    fn name(&self) -> &'static str {
+        "heavy-change"
+    }
+
+    fn select(&mut self, window: &HHDWindow) -> Vec<HeavyHitter> {
+        self.detection
+            .detect(window)
+            .iter()
+            .filter_map(|change| HeavyHitter::for_change(window, change))
+            .collect()
+    }
+
+    fn threshold(&self) -> Option<u64> {
+        self.detection.limit()
+    }
+}
+
+// the prefix with the largest count of all its flows together
+pub struct PrefixAggregatedPolicy {
+    pub prefix_length: u16,
+}
+
+impl HeavyHitterPolicy for PrefixAggregatedPolicy {
+    fn name(&self) -> &'static str {
+        "prefix-aggregated"
+    }
+
+    fn select(&mut self, window: &HHDWindow) -> Vec<HeavyHitter> {
+        let mask = prefix_mask(self.prefix_length);
+        let mut prefixes: HashMap<u32, Vec<&HHDFlowCount>> = HashMap::new();
+        for flow_count in window.flows.iter().filter(|flow_count| window.count(flow_count) > 0) {
+            prefixes.entry(window.address(&flow_count.flow) & mask).or_insert(Vec::new()).push(flow_count);
+        }
+
+        let mut heavy_hitters: Vec<HeavyHitter> = prefixes
+            .into_iter()
+            .map(|(network, flow_counts)| HeavyHitter::for_prefix(network, self.prefix_length, flow_counts))
+            .collect();
+        heavy_hitters.sort_by(|a, b| match window.metric {
+            HHDMetric::Packets => b.packets.cmp(&a.packets),
+            HHDMetric::Bytes => b.bytes.cmp(&a.bytes),
+        });
+        heavy_hitters.truncate(1);
+        heavy_hitters
+    }
+}
+
+// hierarchical heavy hitters, going from the longest to the shortest prefix length every prefix
+// crossing the threshold with the flows not yet covered by a longer heavy prefix gets diverted
+pub struct HierarchicalPrefixPolicy {
+    pub threshold: HHDThreshold,
+    pub prefix_lengths: Vec<u16>,
+    limit: Option<u64>,
+    max_number_of_heavy_hitters: usize,
+}
+
+impl HierarchicalPrefixPolicy {
+    pub fn new(threshold: HHDThreshold, prefix_lengths: Vec<u16>) -> HierarchicalPrefixPolicy {
+        let mut prefix_lengths = prefix_lengths;
+        prefix_lengths.sort_by(|a, b| b.cmp(a));
+        prefix_lengths.dedup();
+
+        HierarchicalPrefixPolicy {
+            threshold: threshold,
+            prefix_lengths: prefix_lengths,
+            limit: None,
+            max_number_of_heavy_hitters: usize::max_value(),
+        }
+    }
+}
+
+impl HeavyHitterPolicy for HierarchicalPrefixPolicy {
+    fn name(&self) -> &'static str {
+        "hierarchical-prefix"
+    }
+
+    fn select(&mut self, window: &HHDWindow) -> Vec<HeavyHitter> {
+        self.limit = self.threshold
+            .limit_for_window(window.metric, window.window_end.saturating_sub(window.window_start), window.port_rate);
+        let limit = match self.limit {
+            Some(limit) => limit,
+            None => return Vec::new(),
+        };
+
+        let mut remaining: Vec<&HHDFlowCount> = window.flows.iter().filter(|flow_count| window.count(flow_count) > 0).collect();
+        let mut heavy_hitters: Vec<HeavyHitter> = Vec::new();
+        for prefix_length in self.prefix_lengths.iter() {
+            let mask = prefix_mask(*prefix_length);
+            let mut prefixes: HashMap<u32, Vec<&HHDFlowCount>> = HashMap::new();
+            for flow_count in remaining.iter() {
+                prefixes.entry(window.address(&flow_count.flow) & mask).or_insert(Vec::new()).push(*flow_count);
+            }
+
+            let mut level_heavy_hitters: Vec<HeavyHitter> = Vec::new();
+            for (network, flow_counts) in prefixes {
+                let count: u64 = flow_counts.iter().map(|flow_count| window.count(flow_count)).sum();
+                if count >= limit {
+                    level_heavy_hitters.push(HeavyHitter::for_prefix(network, *prefix_length, flow_counts));
+                }
+            }
+
+            // prefixes that don't all fit get covered by their parent prefix, the shortest length takes the largest ones
+            let is_shortest = Some(prefix_length) == self.prefix_lengths.last();
+            if heavy_hitters.len() + level_heavy_hitters.len() > self.max_number_of_heavy_hitters && !is_shortest {
+                continue;
+            }
+            sort_heavy_hitters(&mut level_heavy_hitters, window.metric);
+            level_heavy_hitters.truncate(self.max_number_of_heavy_hitters.saturating_sub(heavy_hitters.len()));
+
+            let heavy_networks: Vec<u32> = level_heavy_hitters.iter().map(|heavy_hitter| heavy_hitter.address_int).collect();
+            remaining.retain(|flow_count| !heavy_networks.contains(&(window.address(&flow_count.flow) & mask)));
+            heavy_hitters.extend(level_heavy_hitters);
+        }
+
+        sort_heavy_hitters(&mut heavy_hitters, window.metric);
+        heavy_hitters
+    }
+
+    fn threshold(&self) -> Option<u64> {
+        self.limit
+    }
+
+    fn set_max_number_of_heavy_hitters(&mut self, max_number_of_heavy_hitters: usize) {
+        self.max_number_of_heavy_hitters = max_number_of_heavy_hitters;
+    }
+}
+
+fn sort_heavy_hitters(heavy_hitters: &mut Vec<HeavyHitter>, metric: HHDMetric) {
+    heavy_hitters.sort_by(|a, b| match metric {
+        HHDMetric::Packets => b.packets.cmp(&a.packets),
+        HHDMetric::Bytes => b.bytes.cmp(&a.bytes),
+    });
+}
+
+// parameters of the built-in policies, each policy uses the ones it needs
+#[derive(Clone, Debug)]
+pub struct HHDPolicySettings {
+    pub threshold: Option<HHDThreshold>,
+    pub k: usize,
+    pub prefix_length: u16,
+    pub prefix_lengths: Vec<u16>,
+    pub change_ratio: Option<f64>,
+}
+
+pub fn create_policy(name: &str, settings: &HHDPolicySettings) -> Result<Box<HeavyHitterPolicy>, String> {
+    // a prefix of length 0 would divert all traffic
+    let is_invalid = |prefix_length: &u16| *prefix_length == 0 || *prefix_length > 32;
+    if is_invalid(&settings.prefix_length) || settings.prefix_lengths.iter().any(is_invalid) {
+        return Err("prefix lengths have to be between 1 and 32".to_string());
+    }
+
+    match (name, settings.threshold) {
+        ("max-count", _) => Ok(Box::new(MaxCountPolicy)),
+        ("top-k", _) => Ok(Box::new(TopKPolicy { k: settings.k })),
+        ("threshold", Some(threshold)) => Ok(Box::new(ThresholdPolicy::new(threshold))),
+        ("heavy-change", _) if settings.threshold.is_some() || settings.change_ratio.is_some() => {
+            Ok(Box::new(HeavyChangePolicy::new(settings.threshold, settings.change_ratio)))
+        }
+        ("prefix-aggregated", _) => Ok(Box::new(PrefixAggregatedPolicy {
+            prefix_length: settings.prefix_length,
+        })),
+        ("hierarchical-prefix", _) if settings.prefix_lengths.is_empty() => {
+            Err("policy hierarchical-prefix needs at least one prefix length".to_string())
+        }
+        ("hierarchical-prefix", Some(threshold)) => Ok(Box::new(HierarchicalPrefixPolicy::new(threshold, settings.prefix_lengths.clone()))),
+        ("heavy-change", _) => Err("policy heavy-change needs a threshold or a change ratio".to_string()),
+        ("threshold", None) | ("hierarchical-prefix", None) => Err(format!("policy {} needs a threshold", name)),
+        _ => Err(format!(
+            "unknown policy {}, use max-count, top-k, threshold, heavy-change, prefix-aggregated or hierarchical-prefix",
+            name
+        )),
+    }
+}
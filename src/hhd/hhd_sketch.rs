@@ -0,0 +1,111 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2018 AT&T. All other rights reserved.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+use flows::Flow;
+use std::f64::consts::E;
+
+// rows the P4 program counts, the controller reads the first sketch rows of them
+pub const MAX_SKETCH_ROWS: usize = 4;
+
+// count-min sketch of the HHD counters, the width has to match HASH_WIDTH of the P4 program
+#[derive(Clone, Copy, Debug)]
+pub struct HHDSketch {
+    pub rows: usize,
+    pub width: u32,
+}
+
+impl HHDSketch {
+    pub fn new(rows: usize, width: u32) -> Result<HHDSketch, String> {
+        if rows < 1 || rows > MAX_SKETCH_ROWS {
+            return Err(format!("sketch rows have to be between 1 and {}", MAX_SKETCH_ROWS));
+        }
+        if width < 2 || width > 65536 || !width.is_power_of_two() {
+            return Err("sketch width has to be a power of two up to 65536".to_string());
+        }
+
+        Ok(HHDSketch { rows: rows, width: width })
+    }
+
+    // counter index of the flow in each row
+    pub fn hashes(&self, flow: &Flow) -> Vec<u16> {
+        flow.sketch_hashes.iter().take(self.rows).cloned().collect()
+    }
+
+    // an estimate exceeds the true count by at most epsilon times the total count...
+    pub fn epsilon(&self) -> f64 {
+        E / self.width as f64
+    }
+
+    // ...except with probability delta
+    pub fn delta(&self) -> f64 {
+        (-(self.rows as f64)).exp()
+    }
+}
+
+// snapshot of all sketch counters, a window counts the difference to the snapshot taken at its start
+#[derive(Clone, Debug)]
+pub struct HHDCounters {
+    counts: Vec<Vec<(u64, u64)>>,
+}
+
+impl HHDCounters {
+    pub fn new(counts: Vec<Vec<(u64, u64)>>) -> HHDCounters {
+        HHDCounters { counts: counts }
+    }
+
+    pub fn empty() -> HHDCounters {
+        HHDCounters { counts: Vec::new() }
+    }
+
+    // count-min estimate of packets and bytes since the baseline, the smallest difference across the rows,
+    // counters cleared in between count from 0
+    pub fn estimate(&self, baseline: &HHDCounters, hashes: &Vec<u16>) -> (u64, u64) {
+        if hashes.is_empty() {
+            return (0, 0);
+        }
+
+        let mut packets = u64::max_value();
+        let mut bytes = u64::max_value();
+        for (row, hash) in hashes.iter().enumerate() {
+            let (current_packets, current_bytes) = self.count(row, *hash);
+            let (baseline_packets, baseline_bytes) = baseline.count(row, *hash);
+            let row_packets = match current_packets >= baseline_packets {
+                true => current_packets - baseline_packets,
+                false => current_packets,
+            };
+            let row_bytes = match current_bytes >= baseline_bytes {
+                true => current_bytes - baseline_bytes,
+                false => current_bytes,
+            };
+            packets = packets.min(row_packets);
+            bytes = bytes.min(row_bytes);
+        }
+        (packets, bytes)
+    }
+
+    fn count(&self, row: usize, index: u16) -> (u64, u64) {
+        match self.counts.get(row) {
+            Some(counts) => counts.get(index as usize).cloned().unwrap_or((0, 0)),
+            None => (0, 0),
+        }
+    }
+}
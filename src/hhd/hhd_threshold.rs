@@ -0,0 +1,76 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2018 AT&T. All other rights reserved.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+use hhd::HHDMetric;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HHDThreshold {
+    // packets or bytes within one analysis window
+    PerWindow(u64),
+    // packets or bits per second
+    PerSecond(f64),
+    // percent of the packet or bit rate of the counted ingress ports
+    PercentOfPort(f64),
+}
+
+impl HHDThreshold {
+    // a threshold of 0 or below would make every flow a heavy hitter
+    pub fn from_name(unit: &str, value: f64) -> Result<HHDThreshold, String> {
+        if !value.is_finite() || value <= 0.0 {
+            return Err(format!("threshold {} has to be a number above 0", value));
+        }
+
+        match unit {
+            "per-window" => Ok(HHDThreshold::PerWindow(value.ceil() as u64)),
+            "per-second" => Ok(HHDThreshold::PerSecond(value)),
+            "percent-of-port" => Ok(HHDThreshold::PercentOfPort(value)),
+            _ => Err(format!("unknown threshold unit {}, use per-window, per-second or percent-of-port", unit)),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            HHDThreshold::PerWindow(_) => "per-window",
+            HHDThreshold::PerSecond(_) => "per-second",
+            HHDThreshold::PercentOfPort(_) => "percent-of-port",
+        }
+    }
+
+    // smallest packet or byte count within the window that crosses the threshold,
+    // port_rate is in packets or bits per second, None until the metrics collector measured it
+    pub fn limit_for_window(&self, metric: HHDMetric, window_in_millis: u64, port_rate: Option<f64>) -> Option<u64> {
+        let window_in_seconds = window_in_millis as f64 / 1000.0;
+        let bits_per_unit = match metric {
+            HHDMetric::Packets => 1.0,
+            HHDMetric::Bytes => 8.0,
+        };
+
+        match *self {
+            HHDThreshold::PerWindow(count) => Some(count),
+            HHDThreshold::PerSecond(rate) => Some((rate / bits_per_unit * window_in_seconds).ceil() as u64),
+            HHDThreshold::PercentOfPort(percent) => match port_rate {
+                Some(port_rate) if port_rate > 0.0 => Some((port_rate * percent / 100.0 / bits_per_unit * window_in_seconds).ceil() as u64),
+                _ => None,
+            },
+        }
+    }
+}
@@ -20,8 +20,20 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
 // THE SOFTWARE.
 
+pub use self::hhd_change::{HHDChangeDetection, HHDFlowChange};
+pub use self::hhd_hysteresis::HHDHysteresis;
 pub use self::hhd_layer::HHDLayer;
-pub use self::hhd_manager::HHDManager;
+pub use self::hhd_manager::{HHDFlowCount, HHDManager, HHDMetric, HHDSessionStatus, HHDStatus, HHDWindowResult, DEFAULT_HHD_SESSION};
+pub use self::hhd_policy::{create_policy, merge_heavy_hitters, prefix_mask, HHDPolicySettings, HHDWindow, HeavyChangePolicy, HeavyHitter,
+                           HeavyHitterPolicy, HierarchicalPrefixPolicy, MaxCountPolicy, PrefixAggregatedPolicy, ThresholdPolicy,
+                           TopKPolicy};
+pub use self::hhd_sketch::{HHDCounters, HHDSketch, MAX_SKETCH_ROWS};
+pub use self::hhd_threshold::HHDThreshold;
 
+mod hhd_change;
+mod hhd_hysteresis;
 mod hhd_layer;
 mod hhd_manager;
+mod hhd_policy;
+mod hhd_sketch;
+mod hhd_threshold;
@@ -97,4 +97,8 @@ impl HWManager {
 
         result
     }
+
+    pub fn is_port_up(dev_port: u32) -> bool {
+        BFLayer::get_port_oper_state(dev_port as i32)
+    }
 }
@@ -32,6 +32,9 @@ pub mod L2Layer {
     use std::mem;
     use std::os::raw::c_int;
 
+    // drops win over diverts of the same packets, only drop rules use this priority
+    const DROP_PRIORITY: c_int = 0;
+
     pub fn init() -> u32 {
         unsafe {
             let session_handler: *mut p4_pd_sess_hdl_t = malloc(mem::size_of::<p4_pd_sess_hdl_t>()) as *mut p4_pd_sess_hdl_t;
@@ -70,7 +73,7 @@ pub mod L2Layer {
         ip_address: u32,
         mask: u32,
         high_priority: bool,
-    ) {
+    ) -> u32 {
         unsafe {
             let match_spec: *mut p4_pd_l2_switching_divert_match_spec_t =
                 malloc(mem::size_of::<p4_pd_l2_switching_divert_match_spec_t>()) as *mut p4_pd_l2_switching_divert_match_spec_t;
@@ -112,6 +115,91 @@ pub mod L2Layer {
                 entry_hdl,
             );
             println!("Added Detour Dest, Handle {}", *entry_hdl);
+
+            *entry_hdl
+        }
+    }
+
+    pub fn set_drop(session_handler: u32, divert_type: DivertType, dev_port_ingress: u16, ip_address: u32, mask: u32) -> u32 {
+        unsafe {
+            let match_spec: *mut p4_pd_l2_switching_divert_match_spec_t =
+                malloc(mem::size_of::<p4_pd_l2_switching_divert_match_spec_t>()) as *mut p4_pd_l2_switching_divert_match_spec_t;
+            let entry_hdl: *mut p4_pd_entry_hdl_t = malloc(mem::size_of::<p4_pd_entry_hdl_t>()) as *mut p4_pd_entry_hdl_t;
+
+            (*match_spec).ig_intr_md_ingress_port = dev_port_ingress;
+
+            match divert_type {
+                DivertType::IPSrc => {
+                    (*match_spec).ipv4_srcAddr = ip_address;
+                    (*match_spec).ipv4_srcAddr_mask = mask;
+                    (*match_spec).ipv4_dstAddr = 0;
+                    (*match_spec).ipv4_dstAddr_mask = 0;
+                }
+                DivertType::IPDest => {
+                    (*match_spec).ipv4_dstAddr = ip_address;
+                    (*match_spec).ipv4_dstAddr_mask = mask;
+                    (*match_spec).ipv4_srcAddr = 0;
+                    (*match_spec).ipv4_srcAddr_mask = 0;
+                }
+            }
+
+            p4_pd_l2_switching_divert_table_add_with__drop(session_handler, resolve_dev_target(), match_spec, DROP_PRIORITY, entry_hdl);
+            println!("Added Drop, Handle {}", *entry_hdl);
+
+            *entry_hdl
+        }
+    }
+
+    // handles of deleted rules get reused, the rule only gets deleted if it is still the one that got added,
+    // dev_port_egress is None for drop rules
+    pub fn delete_divert_entry(
+        session_handler: u32,
+        handle: u32,
+        divert_type: DivertType,
+        dev_port_ingress: u16,
+        dev_port_egress: Option<u16>,
+        ip_address: u32,
+        mask: u32,
+    ) -> bool {
+        unsafe {
+            let match_spec: *mut p4_pd_l2_switching_divert_match_spec_t =
+                malloc(mem::size_of::<p4_pd_l2_switching_divert_match_spec_t>()) as *mut p4_pd_l2_switching_divert_match_spec_t;
+            let priority: *mut c_int = malloc(mem::size_of::<c_int>()) as *mut c_int;
+            let action_spec: *mut p4_pd_l2_switching_action_specs_t =
+                malloc(mem::size_of::<p4_pd_l2_switching_action_specs_t>()) as *mut p4_pd_l2_switching_action_specs_t;
+
+            let status = p4_pd_l2_switching_divert_get_entry(
+                session_handler,
+                0 as u8,
+                handle as p4_pd_entry_hdl_t,
+                true,
+                match_spec,
+                priority,
+                action_spec,
+            );
+            if status != 0 {
+                println!("Divert Rule, Handle {} is gone", handle);
+                return false;
+            }
+
+            let (address, address_mask) = match divert_type {
+                DivertType::IPSrc => ((*match_spec).ipv4_srcAddr, (*match_spec).ipv4_srcAddr_mask),
+                DivertType::IPDest => ((*match_spec).ipv4_dstAddr, (*match_spec).ipv4_dstAddr_mask),
+            };
+            let same_action = match dev_port_egress {
+                Some(dev_port_egress) => {
+                    *priority != DROP_PRIORITY && (*action_spec).u.p4_pd_l2_switching_set_egr.action_egress_spec == dev_port_egress
+                }
+                None => *priority == DROP_PRIORITY,
+            };
+            if (*match_spec).ig_intr_md_ingress_port != dev_port_ingress || address != ip_address || address_mask != mask || !same_action {
+                println!("Divert Rule, Handle {} got replaced by another rule", handle);
+                return false;
+            }
+
+            let status = p4_pd_l2_switching_divert_table_delete(session_handler, 0 as u8, handle as p4_pd_entry_hdl_t);
+            println!("Delete Divert Rule, Handle {}, Status {}", handle, status);
+            status == 0
         }
     }
 
@@ -194,7 +282,8 @@ pub mod L2Layer {
                 action_spec,
             );
 
-            if status == 0 {
+            // drop rules have no egress port
+            if status == 0 && *priority != DROP_PRIORITY {
                 if dev_port_ingress == (*match_spec).ig_intr_md_ingress_port
                     && dev_port_egress == (*action_spec).u.p4_pd_l2_switching_set_egr.action_egress_spec
                 {
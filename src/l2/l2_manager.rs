@@ -20,6 +20,7 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
 // THE SOFTWARE.
 
+use event::{EventManager, EventType};
 use hw::HWManager;
 use l2::L2Layer;
 use label::LabelingManager;
@@ -27,7 +28,7 @@ use std::net::Ipv4Addr;
 use std::str::FromStr;
 use std::sync::Mutex;
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum DivertType {
     IPSrc,
     IPDest,
@@ -41,6 +42,14 @@ pub struct Connection {
     pub Type: String,
 }
 
+#[derive(Clone, Debug, RustcEncodable)]
+pub struct DivertEvent {
+    pub divert_type: Option<String>,
+    pub port_ingress: Option<u32>,
+    pub port_egress: Option<u32>,
+    pub ip_address: Option<String>,
+}
+
 pub struct L2Manager {
     session_handler: u32,
 }
@@ -70,7 +79,7 @@ impl L2Manager {
         ip_address: &String,
         ip_prefix_length: u16,
         high_priority: bool,
-    ) {
+    ) -> u32 {
         let dev_port_ingress = HWManager::convert_chassis_port_to_dev_port(&chassis_port_ingress);
         let dev_port_egress = HWManager::convert_chassis_port_to_dev_port(&chassis_port_egress);
         let ip_address_as_int = convert_ip_address_to_int(ip_address);
@@ -78,7 +87,7 @@ impl L2Manager {
 
         println!("Detour {:?} {} {} {} {}", divert_type, dev_port_ingress, dev_port_egress, ip_address_as_int, mask);
 
-        L2Layer::set_divert(
+        let entry_handle = L2Layer::set_divert(
             MANAGER.lock().unwrap().session_handler,
             divert_type.clone(),
             dev_port_ingress as u16,
@@ -94,11 +103,101 @@ impl L2Manager {
             chassis_port_egress,
             format!{"{}/{}", ip_address, ip_prefix_length},
         );
+
+        EventManager::publish(
+            EventType::DivertAdded,
+            &DivertEvent {
+                divert_type: Some(format!{"{:?}", divert_type}),
+                port_ingress: Some(chassis_port_ingress),
+                port_egress: Some(chassis_port_egress),
+                ip_address: Some(format!{"{}/{}", ip_address, ip_prefix_length}),
+            },
+        );
+
+        entry_handle
+    }
+
+    pub fn set_drop(divert_type: DivertType, chassis_port_ingress: u32, ip_address: &String, ip_prefix_length: u16) -> u32 {
+        let dev_port_ingress = HWManager::convert_chassis_port_to_dev_port(&chassis_port_ingress);
+        let ip_address_as_int = convert_ip_address_to_int(ip_address);
+        let mask = convert_prefix_to_mask(ip_prefix_length as u32);
+
+        println!("Drop {:?} {} {} {}", divert_type, dev_port_ingress, ip_address_as_int, mask);
+
+        let entry_handle = L2Layer::set_drop(
+            MANAGER.lock().unwrap().session_handler,
+            divert_type.clone(),
+            dev_port_ingress as u16,
+            ip_address_as_int,
+            mask,
+        );
+
+        LabelingManager::label_drop(format!{"{:?}", divert_type}, chassis_port_ingress, format!{"{}/{}", ip_address, ip_prefix_length});
+
+        EventManager::publish(
+            EventType::DivertAdded,
+            &DivertEvent {
+                divert_type: Some(format!{"{:?}", divert_type}),
+                port_ingress: Some(chassis_port_ingress),
+                port_egress: None,
+                ip_address: Some(format!{"{}/{}", ip_address, ip_prefix_length}),
+            },
+        );
+
+        entry_handle
+    }
+
+    // removes a single divert or drop rule by the handle set_divert or set_drop returned
+    pub fn remove_divert_entry(
+        entry_handle: u32,
+        divert_type: DivertType,
+        chassis_port_ingress: u32,
+        chassis_port_egress: Option<u32>,
+        ip_address: &String,
+        ip_prefix_length: u16,
+    ) {
+        let dev_port_ingress = HWManager::convert_chassis_port_to_dev_port(&chassis_port_ingress);
+        let dev_port_egress = chassis_port_egress.map(|port| HWManager::convert_chassis_port_to_dev_port(&port) as u16);
+        let ip_address_as_int = convert_ip_address_to_int(ip_address);
+        let mask = convert_prefix_to_mask(ip_prefix_length as u32);
+
+        let deleted = L2Layer::delete_divert_entry(
+            MANAGER.lock().unwrap().session_handler,
+            entry_handle,
+            divert_type.clone(),
+            dev_port_ingress as u16,
+            dev_port_egress,
+            ip_address_as_int,
+            mask,
+        );
+        if !deleted {
+            return;
+        }
+
+        EventManager::publish(
+            EventType::DivertRemoved,
+            &DivertEvent {
+                divert_type: Some(format!{"{:?}", divert_type}),
+                port_ingress: Some(chassis_port_ingress),
+                port_egress: chassis_port_egress,
+                ip_address: Some(format!{"{}/{}", ip_address, ip_prefix_length}),
+            },
+        );
     }
 
     pub fn reset_divert_table() {
         L2Layer::reset_divert_table(MANAGER.lock().unwrap().session_handler);
         LabelingManager::label_reset();
+
+        EventManager::publish(
+            EventType::DivertRemoved,
+            &DivertEvent {
+                divert_type: None,
+                port_ingress: None,
+                port_egress: None,
+                ip_address: None,
+            },
+        );
     }
 
     pub fn reset_divert_for_ingress_egress_port(chassis_port_ingress: u32, chassis_port_egress: u32) {
@@ -112,14 +211,24 @@ impl L2Manager {
         );
 
         LabelingManager::label_reset_ingress_egress(chassis_port_ingress, chassis_port_egress);
+
+        EventManager::publish(
+            EventType::DivertRemoved,
+            &DivertEvent {
+                divert_type: None,
+                port_ingress: Some(chassis_port_ingress),
+                port_egress: Some(chassis_port_egress),
+                ip_address: None,
+            },
+        );
     }
 }
 
 fn convert_prefix_to_mask(prefix: u32) -> u32 {
-    if prefix == 32 {
-        u32::max_value()
-    } else {
-        (2u32.pow(prefix) - 1 << (32 - prefix))
+    match prefix {
+        0 => 0,
+        prefix if prefix >= 32 => u32::max_value(),
+        prefix => u32::max_value() << (32 - prefix),
     }
 }
 
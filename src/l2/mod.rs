@@ -21,7 +21,7 @@
 // THE SOFTWARE.
 
 pub use self::l2_layer::L2Layer;
-pub use self::l2_manager::{Connection, DivertType, L2Manager};
+pub use self::l2_manager::{Connection, DivertEvent, DivertType, L2Manager};
 
 mod l2_layer;
 mod l2_manager;
@@ -0,0 +1,264 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2018 AT&T. All other rights reserved.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+use hyper::Client;
+use hyper::header::{Authorization, Basic, Connection};
+use hyper::status::StatusCode;
+use label::{LabelingCredentials, LabelingSink};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+const MAX_BACKOFF_IN_SECONDS: u64 = 60;
+const HTTP_TIMEOUT_IN_SECONDS: u64 = 10;
+
+enum WriteResult {
+    Written,
+    // InfluxDB refused the lines themselves, writing them again won't help
+    Rejected(String),
+    Failed(String),
+}
+
+// writes queued lines in batches, lines that can't be written get spooled to disk and written again later
+pub struct LabelWriter {
+    address: String,
+    credentials: LabelingCredentials,
+    spool_file: String,
+    batch_size: usize,
+    batch_interval: Duration,
+    client: Client,
+    spooled: bool,
+    backoff_in_seconds: u64,
+    next_retry: Instant,
+}
+
+impl LabelWriter {
+    pub fn new(sink: &LabelingSink) -> LabelWriter {
+        let mut client = Client::new();
+        client.set_read_timeout(Some(Duration::from_secs(HTTP_TIMEOUT_IN_SECONDS)));
+        client.set_write_timeout(Some(Duration::from_secs(HTTP_TIMEOUT_IN_SECONDS)));
+
+        // labels spooled before a restart get written first
+        let spooled = match fs::metadata(&sink.spool_file) {
+            Ok(metadata) => metadata.len() > 0,
+            Err(_) => false,
+        };
+        if spooled {
+            println!("Labels spooled in {} from a previous run", sink.spool_file);
+        }
+
+        LabelWriter {
+            address: sink.write_url(),
+            credentials: sink.credentials(),
+            spool_file: sink.spool_file.clone(),
+            batch_size: sink.batch_size,
+            batch_interval: Duration::from_millis(sink.batch_interval_in_millis),
+            client: client,
+            spooled: spooled,
+            backoff_in_seconds: 0,
+            next_retry: Instant::now(),
+        }
+    }
+
+    pub fn run(mut self, receiver: Receiver<String>) {
+        loop {
+            let (batch, disconnected) = self.collect_batch(&receiver);
+
+            if self.spooled {
+                // keep the order, new lines queue up behind the spooled ones
+                self.spool(&batch);
+                self.write_spool();
+            } else if !batch.is_empty() {
+                match self.write(&batch) {
+                    WriteResult::Written => println!("Labeled: {} lines", batch.len()),
+                    WriteResult::Rejected(_) => if let Err((written, err)) = self.write_line_by_line(&batch) {
+                        println!("Labeling failed, spooling {} lines: {}", batch.len() - written, err);
+                        self.spool(&batch[written..]);
+                        self.schedule_retry();
+                    },
+                    WriteResult::Failed(err) => {
+                        println!("Labeling failed, spooling {} lines: {}", batch.len(), err);
+                        self.spool(&batch);
+                        self.schedule_retry();
+                    }
+                }
+            }
+
+            if disconnected {
+                return;
+            }
+        }
+    }
+
+    // waits for the first line at most one batch interval, then collects until the batch is full or the interval is over
+    fn collect_batch(&self, receiver: &Receiver<String>) -> (Vec<String>, bool) {
+        let mut batch = Vec::new();
+        let deadline = Instant::now() + self.batch_interval;
+
+        while batch.len() < self.batch_size {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            match receiver.recv_timeout(deadline - now) {
+                Ok(line) => batch.push(line),
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => return (batch, true),
+            }
+        }
+        (batch, false)
+    }
+
+    fn write(&self, lines: &[String]) -> WriteResult {
+        let body = lines.join("\n");
+        let request = self.client.post(&self.address).header(Connection::close()).body(&body);
+        let response = match self.credentials {
+            LabelingCredentials::None => request.send(),
+            LabelingCredentials::Basic(ref username, ref password) => request
+                .header(Authorization(Basic {
+                    username: username.clone(),
+                    password: password.clone(),
+                }))
+                .send(),
+            LabelingCredentials::Token(ref token) => request.header(Authorization(format!{"Token {}", token})).send(),
+        };
+
+        match response {
+            Ok(ref response) if response.status.is_success() => WriteResult::Written,
+            Ok(ref response) if response.status == StatusCode::BadRequest => WriteResult::Rejected(response.status.to_string()),
+            Ok(response) => WriteResult::Failed(response.status.to_string()),
+            Err(err) => WriteResult::Failed(err.to_string()),
+        }
+    }
+
+    // one malformed line gets the whole batch rejected, line by line only the malformed ones get set aside,
+    // returns how many lines got through if writing fails
+    fn write_line_by_line(&self, lines: &[String]) -> Result<(), (usize, String)> {
+        for (index, line) in lines.iter().enumerate() {
+            match self.write(&lines[index..index + 1]) {
+                WriteResult::Written => {}
+                WriteResult::Rejected(err) => self.set_aside(line, &err),
+                WriteResult::Failed(err) => return Err((index, err)),
+            }
+        }
+        Ok(())
+    }
+
+    fn set_aside(&self, line: &str, err: &str) {
+        let rejected_file = format!{"{}.rejected", self.spool_file};
+        match append_lines(&rejected_file, &[line.to_string()]) {
+            Ok(()) => println!("Label rejected {}, set aside in {}: {}", err, rejected_file, line),
+            Err(io_err) => println!("Label rejected {}, lost {}: {}", err, io_err, line),
+        }
+    }
+
+    fn spool(&mut self, lines: &[String]) {
+        if lines.is_empty() {
+            return;
+        }
+
+        match append_lines(&self.spool_file, lines) {
+            Ok(()) => self.spooled = true,
+            Err(err) => println!("Labels can't be spooled to {}, lost {}: {}", self.spool_file, err, lines.join("\n")),
+        }
+    }
+
+    // writes the spooled lines batch by batch, whatever is left stays spooled until the next retry
+    fn write_spool(&mut self) {
+        if Instant::now() < self.next_retry {
+            return;
+        }
+
+        let lines: Vec<String> = match File::open(&self.spool_file) {
+            Ok(file) => BufReader::new(file).lines().filter_map(|line| line.ok()).filter(|line| !line.is_empty()).collect(),
+            Err(err) => {
+                println!("Labels spool {} can't be read: {}", self.spool_file, err);
+                self.schedule_retry();
+                return;
+            }
+        };
+
+        let mut written = 0;
+        let mut failed = false;
+        for chunk in lines.chunks(self.batch_size) {
+            match self.write(chunk) {
+                WriteResult::Written => {}
+                WriteResult::Rejected(_) => if let Err((chunk_written, err)) = self.write_line_by_line(chunk) {
+                    written += chunk_written;
+                    println!("Labeling still failing, {} lines spooled: {}", lines.len() - written, err);
+                    failed = true;
+                    break;
+                },
+                WriteResult::Failed(err) => {
+                    println!("Labeling still failing, {} lines spooled: {}", lines.len() - written, err);
+                    failed = true;
+                    break;
+                }
+            }
+            written += chunk.len();
+        }
+
+        if written > 0 {
+            self.rewrite_spool(&lines[written..]);
+            println!("Labeled: {} spooled lines", written);
+        }
+
+        if failed {
+            self.schedule_retry();
+        } else {
+            self.backoff_in_seconds = 0;
+        }
+    }
+
+    // the remaining lines replace the spool file in one rename
+    fn rewrite_spool(&mut self, lines: &[String]) {
+        if lines.is_empty() {
+            let _ = fs::remove_file(&self.spool_file);
+            self.spooled = false;
+            return;
+        }
+
+        let temporary_file = format!{"{}.tmp", self.spool_file};
+        let _ = fs::remove_file(&temporary_file);
+        match append_lines(&temporary_file, lines).and_then(|_| fs::rename(&temporary_file, &self.spool_file)) {
+            Ok(()) => {}
+            Err(err) => println!("Labels spool {} can't be rewritten, written lines stay spooled: {}", self.spool_file, err),
+        }
+    }
+
+    fn schedule_retry(&mut self) {
+        self.backoff_in_seconds = match self.backoff_in_seconds {
+            0 => 1,
+            backoff_in_seconds => (backoff_in_seconds * 2).min(MAX_BACKOFF_IN_SECONDS),
+        };
+        self.next_retry = Instant::now() + Duration::from_secs(self.backoff_in_seconds);
+    }
+}
+
+fn append_lines(path: &str, lines: &[String]) -> io::Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    for line in lines {
+        writeln!(file, "{}", line)?;
+    }
+    file.sync_data()
+}
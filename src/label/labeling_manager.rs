@@ -20,57 +20,107 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
 // THE SOFTWARE.
 
-use hyper::Client;
-use hyper::header::Connection;
+use label::{LabelWriter, LabelingSink};
 use std::sync::Mutex;
+use std::sync::mpsc::{channel, Sender};
 use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 pub struct LabelingManager {
     labeling_on: bool,
+    sink: LabelingSink,
+    queue: Option<Sender<String>>,
 }
 
 lazy_static! {
-    static ref MANAGER: Mutex<LabelingManager> = Mutex::new(LabelingManager{labeling_on: false});
-}
-
-lazy_static! {
-    static ref CLIENT: Client = Client::new();
+    static ref MANAGER: Mutex<LabelingManager> = Mutex::new(LabelingManager{labeling_on: false, sink: LabelingSink::new(), queue: None});
 }
 
 impl LabelingManager {
-    pub fn run(labeling_on: bool) {
-        MANAGER.lock().unwrap().labeling_on = labeling_on;
-        println!("LabelingManager running: {}", labeling_on);
+    pub fn run(labeling_on: bool, sink: LabelingSink) {
+        let mut manager = MANAGER.lock().unwrap();
+        manager.labeling_on = labeling_on;
+        println!("LabelingManager running: {}, writing to {}", labeling_on, sink.write_url());
+
+        if labeling_on {
+            let (sender, receiver) = channel();
+            let writer = LabelWriter::new(&sink);
+            let _ = thread::Builder::new().name("label-writer".to_string()).spawn(move || writer.run(receiver));
+            manager.queue = Some(sender);
+        }
+        manager.sink = sink;
     }
 
     pub fn label_reset() {
         if MANAGER.lock().unwrap().labeling_on {
-            let data = "label,type=reset,ingress=999,egress=999 data=\"reset\"".to_string();
+            let data = "type=reset,ingress=999,egress=999 data=\"reset\"".to_string();
             send_label(data);
         }
     }
 
     pub fn label_reset_ingress_egress(ingress: u32, egress: u32) {
         if MANAGER.lock().unwrap().labeling_on {
-            let data = format!{"label,type=reset,ingress={},egress={} data=\"reset\"", ingress, egress};
+            let data = format!{"type=reset,ingress={},egress={} data=\"reset\"", ingress, egress};
             send_label(data);
         }
     }
 
     pub fn label_divert(divert_type: String, ingress: u32, egress: u32, address: String) {
         if MANAGER.lock().unwrap().labeling_on {
-            let data = format!{"label,type=divert,ingress={},egress={},divert-type={} data=\"{}\"", ingress, egress, divert_type, address};
+            let data = format!{"type=divert,ingress={},egress={},divert-type={} data=\"{}\"", ingress, egress, divert_type, address};
+            send_label(data);
+        }
+    }
+
+    pub fn label_drop(divert_type: String, ingress: u32, address: String) {
+        if MANAGER.lock().unwrap().labeling_on {
+            let data = format!{"type=drop,ingress={},divert-type={} data=\"{}\"", ingress, divert_type, address};
             send_label(data);
         }
     }
+
+    pub fn label_detection(kind: &str, address: String, number_of_peers: usize, action: &str) {
+        if MANAGER.lock().unwrap().labeling_on {
+            let data = format!{"type=detection,kind={},action={} data=\"{}\",peers={}i", kind, action, address, number_of_peers};
+            send_label(data);
+        }
+    }
+
+    // time series points in line protocol with nanosecond timestamps, the writer batches them with the labels
+    pub fn write_points(points: Vec<(String, u64)>) {
+        let manager = MANAGER.lock().unwrap();
+        if manager.labeling_on && !points.is_empty() {
+            for &(ref point, timestamp) in points.iter() {
+                manager.enqueue(manager.sink.point_line(point, timestamp));
+            }
+        }
+    }
+
+    pub fn label_heavy_change(metric: &str, flow: String, protocol: u8, delta: i64, ratio: Option<f64>) {
+        if MANAGER.lock().unwrap().labeling_on {
+            let mut data = format!{"type=heavy-change,metric={},protocol={} data=\"{}\",delta={}i", metric, protocol, flow, delta};
+            if let Some(ratio) = ratio {
+                data.push_str(&format!{",ratio={}", ratio});
+            }
+            send_label(data);
+        }
+    }
+
+    fn enqueue(&self, line: String) {
+        if let Some(ref queue) = self.queue {
+            let _ = queue.send(line);
+        }
+    }
 }
 
+// data are the tags and fields of the label, measurement and static tags come from the sink, the event time is now
 fn send_label(data: String) {
-    let address = format!{"http://localhost:8086/write?db=telegraf"};
-    let data_internal = data.clone();
+    let timestamp = current_time_in_nanos();
+    let manager = MANAGER.lock().unwrap();
+    manager.enqueue(manager.sink.label_line(&data, timestamp));
+}
 
-    let _ = thread::Builder::new().name("label reset".to_string()).spawn(move || {
-        let _ = CLIENT.post(&address).header(Connection::close()).body(&data_internal).send();
-        println!("Labeled: {}", data);
-    });
+fn current_time_in_nanos() -> u64 {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+    now.as_secs() * 1000000000 + now.subsec_nanos() as u64
 }
@@ -0,0 +1,184 @@
+// The MIT License (MIT)
+//
+// Copyright (c) 2018 AT&T. All other rights reserved.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
+// THE SOFTWARE.
+
+// where labels and time series points get written to, an InfluxDB v1 database or an InfluxDB v2 bucket
+#[derive(Clone, Debug)]
+pub struct LabelingSink {
+    pub url: String,
+    pub database: String,
+    pub retention_policy: Option<String>,
+    pub precision: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub org: Option<String>,
+    pub bucket: Option<String>,
+    pub token: Option<String>,
+    pub measurement: String,
+    pub tags: Vec<(String, String)>,
+    pub spool_file: String,
+    pub batch_size: usize,
+    pub batch_interval_in_millis: u64,
+}
+
+#[derive(Clone, Debug)]
+pub enum LabelingCredentials {
+    None,
+    Basic(String, Option<String>),
+    Token(String),
+}
+
+impl LabelingSink {
+    pub fn new() -> LabelingSink {
+        LabelingSink {
+            url: "http://localhost:8086".to_string(),
+            database: "telegraf".to_string(),
+            retention_policy: None,
+            precision: "ns".to_string(),
+            username: None,
+            password: None,
+            org: None,
+            bucket: None,
+            token: None,
+            measurement: "label".to_string(),
+            tags: Vec::new(),
+            spool_file: "/var/tmp/taep-labels.spool".to_string(),
+            batch_size: 100,
+            batch_interval_in_millis: 1000,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if precision_divisor(&self.precision).is_none() {
+            return Err(format!("labeling precision {} has to be ns, us, ms or s", self.precision));
+        }
+        if self.is_v2() && (self.org.is_none() || self.bucket.is_none() || self.token.is_none()) {
+            return Err("labeling to InfluxDB v2 needs org, bucket and token".to_string());
+        }
+        if self.measurement.is_empty() {
+            return Err("labeling measurement can't be empty".to_string());
+        }
+        if self.batch_size == 0 || self.batch_interval_in_millis == 0 {
+            return Err("labeling batch-size and batch-interval-in-millis have to be at least 1".to_string());
+        }
+        Ok(())
+    }
+
+    fn is_v2(&self) -> bool {
+        self.org.is_some() || self.bucket.is_some() || self.token.is_some()
+    }
+
+    pub fn write_url(&self) -> String {
+        let url = self.url.trim_end_matches('/');
+        if self.is_v2() {
+            return format!(
+                "{}/api/v2/write?org={}&bucket={}&precision={}",
+                url,
+                encode_query_value(self.org.as_ref().unwrap()),
+                encode_query_value(self.bucket.as_ref().unwrap()),
+                self.precision
+            );
+        }
+
+        // v1 uses n and u for nano and micro seconds
+        let precision = match self.precision.as_str() {
+            "ns" => "n",
+            "us" => "u",
+            precision => precision,
+        };
+        let mut address = format!("{}/write?db={}&precision={}", url, encode_query_value(&self.database), precision);
+        if let Some(ref retention_policy) = self.retention_policy {
+            address.push_str(&format!("&rp={}", encode_query_value(retention_policy)));
+        }
+        address
+    }
+
+    pub fn credentials(&self) -> LabelingCredentials {
+        if let Some(ref token) = self.token {
+            return LabelingCredentials::Token(token.clone());
+        }
+        match self.username {
+            Some(ref username) => LabelingCredentials::Basic(username.clone(), self.password.clone()),
+            None => LabelingCredentials::None,
+        }
+    }
+
+    // label without measurement, e.g. type=reset data="reset", the timestamp is in nanoseconds
+    pub fn label_line(&self, tags_and_fields: &str, timestamp: u64) -> String {
+        format!(
+            "{}{},{} {}",
+            escape_measurement(&self.measurement),
+            self.static_tags(),
+            tags_and_fields,
+            self.timestamp(timestamp)
+        )
+    }
+
+    // complete point, the static tags get added after the measurement, the timestamp is in nanoseconds
+    pub fn point_line(&self, point: &str, timestamp: u64) -> String {
+        let static_tags = self.static_tags();
+        let line = match point.find(',') {
+            Some(index) if !static_tags.is_empty() => format!("{}{}{}", &point[..index], static_tags, &point[index..]),
+            _ => point.to_string(),
+        };
+        format!("{} {}", line, self.timestamp(timestamp))
+    }
+
+    fn timestamp(&self, timestamp: u64) -> u64 {
+        timestamp / precision_divisor(&self.precision).unwrap_or(1)
+    }
+
+    fn static_tags(&self) -> String {
+        self.tags
+            .iter()
+            .map(|&(ref key, ref value)| format!(",{}={}", escape_tag(key), escape_tag(value)))
+            .collect()
+    }
+}
+
+fn precision_divisor(precision: &str) -> Option<u64> {
+    match precision {
+        "ns" => Some(1),
+        "us" => Some(1000),
+        "ms" => Some(1000000),
+        "s" => Some(1000000000),
+        _ => None,
+    }
+}
+
+pub fn escape_tag(value: &str) -> String {
+    value.replace(",", "\\,").replace("=", "\\=").replace(" ", "\\ ")
+}
+
+fn escape_measurement(value: &str) -> String {
+    value.replace(",", "\\,").replace(" ", "\\ ")
+}
+
+fn encode_query_value(value: &str) -> String {
+    let mut result = String::new();
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => result.push(byte as char),
+            _ => result.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    result
+}
@@ -20,6 +20,10 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
 // THE SOFTWARE.
 
+pub use self::label_writer::LabelWriter;
 pub use self::labeling_manager::LabelingManager;
+pub use self::labeling_sink::{escape_tag, LabelingCredentials, LabelingSink};
 
+mod label_writer;
 mod labeling_manager;
+mod labeling_sink;
@@ -32,6 +32,10 @@ extern crate lazy_static;
 
 pub mod api;
 pub mod bf;
+pub mod detection;
+pub mod event;
+pub mod export;
+pub mod feature;
 pub mod flows;
 pub mod hhd;
 pub mod hw;
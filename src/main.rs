@@ -29,11 +29,14 @@ use std::io::Read;
 use std::sync::{Condvar, Mutex};
 use taep_controller::api::APIManager;
 use taep_controller::bf::BFManager;
-use taep_controller::flows::FlowsManager;
-use taep_controller::hhd::HHDManager;
+use taep_controller::detection::{DetectionAction, DetectionManager, DetectionSettings};
+use taep_controller::export::{Collector, ExportManager, ExportProtocol};
+use taep_controller::feature::FeatureManager;
+use taep_controller::flows::{FlowsEvictionPolicy, FlowsManager};
+use taep_controller::hhd::{create_policy, HHDChangeDetection, HHDHysteresis, HHDManager, HHDPolicySettings, HHDSketch, HHDThreshold};
 use taep_controller::hw::{HWManager, Port};
 use taep_controller::l2::{Connection, L2Manager};
-use taep_controller::label::LabelingManager;
+use taep_controller::label::{LabelingManager, LabelingSink};
 use taep_controller::metrics::MetricsCollector;
 use yaml_rust::{Yaml, YamlLoader};
 
@@ -61,7 +64,7 @@ fn main() {
     BFManager::run(bf_config_file, bf_bin_path);
 
     APIManager::run(api_port);
-    LabelingManager::run(enable_labeling);
+    LabelingManager::run(enable_labeling, read_labeling_sink(&config));
     LabelingManager::label_reset();
 
     HWManager::init();
@@ -93,9 +96,41 @@ fn main() {
         }
     }
 
-    HHDManager::init(read_max_number_of_flows(&config), read_analysis_window_in_seconds(&config));
+    FeatureManager::init();
 
-    FlowsManager::init();
+    let (policy_name, policy_settings) = read_hhd_policy(&config);
+    HHDManager::init(
+        read_hhd_sketch(&config),
+        read_max_number_of_flows(&config),
+        read_analysis_window_in_millis(&config),
+        read_hhd_top_k(&config),
+        read_hhd_results_history_size(&config),
+        read_hhd_max_number_of_diverts(&config),
+        read_hhd_hysteresis(&config),
+        read_hhd_change_detection(&config),
+        &policy_name,
+        policy_settings,
+    );
+
+    FlowsManager::init(
+        read_flow_idle_timeout_in_seconds(&config),
+        read_flow_epoch_in_seconds(&config),
+        read_flow_history_size(&config),
+        read_flow_eviction_policy(&config),
+        read_flow_eviction_grace_period_in_seconds(&config),
+    );
+
+    DetectionManager::init(read_detections_history_size(&config));
+    if let Some(settings) = read_detection_settings(&config) {
+        DetectionManager::run(settings).unwrap();
+    }
+
+    ExportManager::run(
+        read_collectors(&config),
+        read_export_interval_in_seconds(&config),
+        read_template_refresh_in_seconds(&config),
+        read_observation_domain_id(&config),
+    );
 
     let poll_interval_in_seconds: u16 = 5;
     MetricsCollector::run(poll_interval_in_seconds);
@@ -172,6 +207,29 @@ fn read_connections(config: &Yaml) -> Vec<Connection> {
     result.clone()
 }
 
+fn read_collectors(config: &Yaml) -> Vec<Collector> {
+    let mut result = Vec::new();
+
+    match config["export"]["collectors"].is_badvalue() {
+        true => {}
+        false => {
+            let collectors = config["export"]["collectors"].as_vec().unwrap();
+            for collector in collectors {
+                let definition = Collector {
+                    Address: collector["address"].as_str().unwrap().to_string(),
+                    Protocol: match collector["protocol"].as_str() {
+                        Some(name) => ExportProtocol::from_name(name).expect("export collector protocol has to be ipfix or netflow-v9"),
+                        None => ExportProtocol::Ipfix,
+                    },
+                };
+                result.push(definition);
+            }
+        }
+    }
+
+    result.clone()
+}
+
 fn read_bf_bin_path(config: &Yaml) -> String {
     match config["bf-bin-path"].is_badvalue() {
         true => "/root/bf-sde/install".to_string(),
@@ -193,6 +251,53 @@ fn read_enable_labeling(config: &Yaml) -> bool {
     }
 }
 
+// labels and HHD time series go to localhost:8086, database telegraf, unless configured otherwise
+fn read_labeling_sink(config: &Yaml) -> LabelingSink {
+    let labeling = &config["labeling"];
+    let mut sink = LabelingSink::new();
+
+    if let Some(url) = labeling["url"].as_str() {
+        sink.url = url.to_string();
+    }
+    if let Some(database) = labeling["database"].as_str() {
+        sink.database = database.to_string();
+    }
+    if let Some(precision) = labeling["precision"].as_str() {
+        sink.precision = precision.to_string();
+    }
+    if let Some(measurement) = labeling["measurement"].as_str() {
+        sink.measurement = measurement.to_string();
+    }
+    sink.retention_policy = labeling["retention-policy"].as_str().map(|value| value.to_string());
+    sink.username = labeling["username"].as_str().map(|value| value.to_string());
+    sink.password = labeling["password"].as_str().map(|value| value.to_string());
+    sink.org = labeling["org"].as_str().map(|value| value.to_string());
+    sink.bucket = labeling["bucket"].as_str().map(|value| value.to_string());
+    sink.token = labeling["token"].as_str().map(|value| value.to_string());
+    if let Some(spool_file) = labeling["spool-file"].as_str() {
+        sink.spool_file = spool_file.to_string();
+    }
+    if let Some(batch_size) = labeling["batch-size"].as_i64() {
+        sink.batch_size = batch_size as usize;
+    }
+    if let Some(batch_interval_in_millis) = labeling["batch-interval-in-millis"].as_i64() {
+        sink.batch_interval_in_millis = batch_interval_in_millis as u64;
+    }
+
+    if let Some(tags) = labeling["tags"].as_hash() {
+        for (key, value) in tags {
+            let value = match value.as_str() {
+                Some(value) => value.to_string(),
+                None => value.as_i64().expect("labeling tags have to be strings or numbers").to_string(),
+            };
+            sink.tags.push((key.as_str().expect("labeling tag names have to be strings").to_string(), value));
+        }
+    }
+
+    sink.validate().unwrap();
+    sink
+}
+
 fn read_api_port(config: &Yaml) -> u16 {
     (match config["api_port"].is_badvalue() {
         true => 8100,
@@ -200,19 +305,217 @@ fn read_api_port(config: &Yaml) -> u16 {
     }) as u16
 }
 
-fn read_analysis_window_in_seconds(config: &Yaml) -> u16 {
-    (match config["hhd"].is_badvalue() {
-        true => 30,
-        false => config["hhd"]["analysis-window-in-seconds"].as_i64().unwrap(),
-    }) as u16
+// sub-second windows like 0.5 are fine
+fn read_analysis_window_in_millis(config: &Yaml) -> u64 {
+    (read_real(&config["hhd"]["analysis-window-in-seconds"]).unwrap_or(30.0) * 1000.0) as u64
 }
 
-fn read_max_number_of_flows(config: &Yaml) -> u16 {
+fn read_max_number_of_flows(config: &Yaml) -> u32 {
     (match config["hhd"].is_badvalue() {
         true => 100,
         false => match config["hhd"]["max-number-of-flows"].is_badvalue() {
             true => 100,
             false => config["hhd"]["max-number-of-flows"].as_i64().unwrap(),
         },
+    }) as u32
+}
+
+fn read_hhd_top_k(config: &Yaml) -> usize {
+    (match config["hhd"]["top-k"].is_badvalue() {
+        true => 10,
+        false => config["hhd"]["top-k"].as_i64().unwrap(),
+    }) as usize
+}
+
+fn read_hhd_results_history_size(config: &Yaml) -> usize {
+    (match config["hhd"]["results-history-size"].is_badvalue() {
+        true => 100,
+        false => config["hhd"]["results-history-size"].as_i64().unwrap(),
+    }) as usize
+}
+
+fn read_hhd_max_number_of_diverts(config: &Yaml) -> usize {
+    (match config["hhd"]["max-number-of-diverts"].is_badvalue() {
+        true => 10,
+        false => config["hhd"]["max-number-of-diverts"].as_i64().unwrap(),
+    }) as usize
+}
+
+// the width has to match 2^HASH_WIDTH the P4 program got compiled with
+fn read_hhd_sketch(config: &Yaml) -> HHDSketch {
+    let rows = (match config["hhd"]["sketch-rows"].is_badvalue() {
+        true => 2,
+        false => config["hhd"]["sketch-rows"].as_i64().unwrap(),
+    }) as usize;
+    let width = (match config["hhd"]["sketch-width"].is_badvalue() {
+        true => 16384,
+        false => config["hhd"]["sketch-width"].as_i64().unwrap(),
+    }) as u32;
+
+    HHDSketch::new(rows, width).unwrap()
+}
+
+fn read_hhd_hysteresis(config: &Yaml) -> HHDHysteresis {
+    let switch_margin_percent = read_real(&config["hhd"]["switch-margin-percent"]).unwrap_or(0.0);
+    assert!(
+        switch_margin_percent.is_finite() && switch_margin_percent >= 0.0,
+        "hhd switch-margin-percent has to be a number of at least 0"
+    );
+    let switch_after_windows = (match config["hhd"]["switch-after-windows"].is_badvalue() {
+        true => 1,
+        false => config["hhd"]["switch-after-windows"].as_i64().unwrap(),
+    }) as u32;
+    let min_hold_time_in_seconds = (match config["hhd"]["min-hold-time-in-seconds"].is_badvalue() {
+        true => 0,
+        false => config["hhd"]["min-hold-time-in-seconds"].as_i64().unwrap(),
+    }) as u32;
+
+    HHDHysteresis::new(switch_margin_percent, switch_after_windows, min_hold_time_in_seconds)
+}
+
+fn read_hhd_change_detection(config: &Yaml) -> HHDChangeDetection {
+    let change_threshold = read_hhd_threshold(config, "change-threshold", "change-threshold-unit");
+    let change_ratio = read_real(&config["hhd"]["change-ratio"]);
+    if let Some(change_ratio) = change_ratio {
+        assert!(change_ratio.is_finite() && change_ratio > 0.0, "hhd change-ratio has to be a number above 0");
+    }
+    let divert_heavy_changes = config["hhd"]["divert-heavy-changes"].as_bool().unwrap_or(false);
+
+    HHDChangeDetection::new(change_threshold, change_ratio, divert_heavy_changes)
+}
+
+fn read_hhd_threshold(config: &Yaml, threshold_key: &str, unit_key: &str) -> Option<HHDThreshold> {
+    match read_real(&config["hhd"][threshold_key]) {
+        Some(value) => {
+            let unit = config["hhd"][unit_key].as_str().unwrap_or("per-window");
+            Some(HHDThreshold::from_name(unit, value).unwrap())
+        }
+        None => None,
+    }
+}
+
+// the policy gets created for every HHD session, an invalid one stops the controller right away
+fn read_hhd_policy(config: &Yaml) -> (String, HHDPolicySettings) {
+    let name = config["hhd"]["policy"].as_str().unwrap_or("max-count");
+    let threshold = read_hhd_threshold(config, "threshold", "threshold-unit");
+    let prefix_length = (match config["hhd"]["prefix-length"].is_badvalue() {
+        true => 24,
+        false => config["hhd"]["prefix-length"].as_i64().unwrap(),
+    }) as u16;
+    let prefix_lengths: Vec<u16> = match config["hhd"]["prefix-lengths"].as_vec() {
+        Some(prefix_lengths) => prefix_lengths.iter().map(|prefix_length| prefix_length.as_i64().unwrap() as u16).collect(),
+        None => vec![32, 24, 16],
+    };
+
+    let settings = HHDPolicySettings {
+        threshold: threshold,
+        k: read_hhd_top_k(config),
+        prefix_length: prefix_length,
+        prefix_lengths: prefix_lengths,
+        change_ratio: read_real(&config["hhd"]["change-ratio"]),
+    };
+    create_policy(name, &settings).unwrap();
+    (name.to_string(), settings)
+}
+
+// yaml reads 10 as integer and 10.5 as real
+fn read_real(value: &Yaml) -> Option<f64> {
+    match value.as_f64() {
+        Some(value) => Some(value),
+        None => value.as_i64().map(|value| value as f64),
+    }
+}
+
+fn read_detections_history_size(config: &Yaml) -> usize {
+    (match config["detections"]["history-size"].is_badvalue() {
+        true => 100,
+        false => config["detections"]["history-size"].as_i64().unwrap(),
+    }) as usize
+}
+
+// detections only run if at least one threshold is configured
+fn read_detection_settings(config: &Yaml) -> Option<DetectionSettings> {
+    let superspreader_threshold = config["detections"]["superspreader-threshold"].as_i64().map(|threshold| threshold as usize);
+    let ddos_target_threshold = config["detections"]["ddos-target-threshold"].as_i64().map(|threshold| threshold as usize);
+    if superspreader_threshold.is_none() && ddos_target_threshold.is_none() {
+        return None;
+    }
+
+    let mut settings = DetectionSettings::new(superspreader_threshold, ddos_target_threshold);
+    if let Some(interval_in_seconds) = config["detections"]["interval-in-seconds"].as_i64() {
+        settings.interval_in_seconds = interval_in_seconds as u32;
+    }
+    if let Some(window_in_seconds) = config["detections"]["window-in-seconds"].as_i64() {
+        settings.window_in_seconds = window_in_seconds as u32;
+    }
+    if let Some(name) = config["detections"]["action"].as_str() {
+        settings.action = DetectionAction::from_name(name).expect("detections action has to be report, divert or drop");
+    }
+    settings.divert_egress = config["detections"]["divert-egress"].as_i64().map(|port| port as u32);
+    if let Some(max_number_of_actions) = config["detections"]["max-number-of-actions"].as_i64() {
+        settings.max_number_of_actions = max_number_of_actions as usize;
+    }
+    Some(settings)
+}
+
+fn read_flow_idle_timeout_in_seconds(config: &Yaml) -> u32 {
+    (match config["flows"].is_badvalue() {
+        true => 60,
+        false => match config["flows"]["idle-timeout-in-seconds"].is_badvalue() {
+            true => 60,
+            false => config["flows"]["idle-timeout-in-seconds"].as_i64().unwrap(),
+        },
+    }) as u32
+}
+
+fn read_flow_epoch_in_seconds(config: &Yaml) -> u32 {
+    (match config["flows"]["epoch-in-seconds"].is_badvalue() {
+        true => 10,
+        false => config["flows"]["epoch-in-seconds"].as_i64().unwrap(),
+    }) as u32
+}
+
+fn read_flow_history_size(config: &Yaml) -> usize {
+    (match config["flows"].is_badvalue() {
+        true => 10000,
+        false => match config["flows"]["history-size"].is_badvalue() {
+            true => 10000,
+            false => config["flows"]["history-size"].as_i64().unwrap(),
+        },
+    }) as usize
+}
+
+fn read_flow_eviction_policy(config: &Yaml) -> FlowsEvictionPolicy {
+    match config["flows"]["eviction-policy"].as_str() {
+        Some(name) => FlowsEvictionPolicy::from_name(name).expect("flows eviction-policy has to be drop-new, lru or evict-smallest"),
+        None => FlowsEvictionPolicy::DropNew,
+    }
+}
+
+fn read_flow_eviction_grace_period_in_seconds(config: &Yaml) -> u32 {
+    (match config["flows"]["eviction-grace-period-in-seconds"].is_badvalue() {
+        true => 10,
+        false => config["flows"]["eviction-grace-period-in-seconds"].as_i64().unwrap(),
+    }) as u32
+}
+
+fn read_export_interval_in_seconds(config: &Yaml) -> u16 {
+    (match config["export"]["interval-in-seconds"].is_badvalue() {
+        true => 10,
+        false => config["export"]["interval-in-seconds"].as_i64().unwrap(),
     }) as u16
 }
+
+fn read_template_refresh_in_seconds(config: &Yaml) -> u32 {
+    (match config["export"]["template-refresh-in-seconds"].is_badvalue() {
+        true => 300,
+        false => config["export"]["template-refresh-in-seconds"].as_i64().unwrap(),
+    }) as u32
+}
+
+fn read_observation_domain_id(config: &Yaml) -> u32 {
+    (match config["export"]["observation-domain-id"].is_badvalue() {
+        true => 0,
+        false => config["export"]["observation-domain-id"].as_i64().unwrap(),
+    }) as u32
+}
@@ -20,6 +20,8 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
 // THE SOFTWARE.
 
+use event::{EventManager, EventType};
+use flows::current_time_in_millis;
 use hw::HWManager;
 use std::collections::HashMap;
 use std::sync::Mutex;
@@ -30,6 +32,13 @@ use std::u16;
 pub struct MetricsCollector {
     poll_interval_in_seconds: u16,
     recent_metrics: HashMap<u32, Metrics>,
+    port_status: HashMap<u32, bool>,
+}
+
+#[derive(Clone, Debug, RustcEncodable)]
+pub struct PortStatus {
+    pub chassis_port: u32,
+    pub up: bool,
 }
 
 #[derive(Clone, Debug, RustcEncodable)]
@@ -40,11 +49,14 @@ pub struct Metrics {
     pub octets_in: u64,
     pub octets_out: u64,
     pub packets_dropped_buffer_full: u64,
+    pub timestamp: u64,
+    pub packets_in_per_second: f64,
+    pub bits_in_per_second: f64,
 }
 
 lazy_static! {
     static ref COLLECTOR: Mutex<MetricsCollector> =
-        Mutex::new(MetricsCollector{poll_interval_in_seconds: u16::MAX, recent_metrics: HashMap::new()});
+        Mutex::new(MetricsCollector{poll_interval_in_seconds: u16::MAX, recent_metrics: HashMap::new(), port_status: HashMap::new()});
 }
 
 impl MetricsCollector {
@@ -65,6 +77,10 @@ impl MetricsCollector {
         }
         result
     }
+
+    pub fn get_port_stats_for_port(chassis_port: u32) -> Option<Metrics> {
+        COLLECTOR.lock().unwrap().recent_metrics.get(&chassis_port).cloned()
+    }
 }
 
 fn collect_port_stats() {
@@ -72,6 +88,19 @@ fn collect_port_stats() {
     for port in configured_ports {
         let chassis_port = HWManager::convert_dev_port_to_chassis_port(&port);
         let stats = HWManager::get_stats_for_port(port);
+        let now = current_time_in_millis();
+
+        // rates cover the time since the previous poll
+        let (packets_in_per_second, bits_in_per_second) = match COLLECTOR.lock().unwrap().recent_metrics.get(&chassis_port) {
+            Some(previous) if now > previous.timestamp => {
+                let seconds = (now - previous.timestamp) as f64 / 1000.0;
+                (
+                    stats.packets_in.saturating_sub(previous.packets_in) as f64 / seconds,
+                    stats.octets_in.saturating_sub(previous.octets_in) as f64 * 8.0 / seconds,
+                )
+            }
+            _ => (0.0, 0.0),
+        };
 
         let metrics = Metrics {
             chassis_port: chassis_port,
@@ -80,8 +109,20 @@ fn collect_port_stats() {
             octets_in: stats.octets_in,
             octets_out: stats.octets_out,
             packets_dropped_buffer_full: stats.packets_dropped_buffer_full,
+            timestamp: now,
+            packets_in_per_second: packets_in_per_second,
+            bits_in_per_second: bits_in_per_second,
         };
 
         COLLECTOR.lock().unwrap().recent_metrics.insert(chassis_port, metrics);
+
+        let up = HWManager::is_port_up(port);
+        match COLLECTOR.lock().unwrap().port_status.insert(chassis_port, up) {
+            Some(previous_up) if previous_up != up => {
+                println!("Port {} changed status to {}", chassis_port, if up { "up" } else { "down" });
+                EventManager::publish(EventType::PortStatus, &PortStatus { chassis_port: chassis_port, up: up });
+            }
+            _ => {}
+        }
     }
 }
@@ -20,6 +20,6 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
 // THE SOFTWARE.
 
-pub use self::metrics_collector::MetricsCollector;
+pub use self::metrics_collector::{MetricsCollector, PortStatus};
 
 mod metrics_collector;